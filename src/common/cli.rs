@@ -1,8 +1,21 @@
 use crate::common::error::AppError;
-use crate::core::variables::ValueType;
+use crate::core::ansible::SIDECAR_SSH_CONFIG;
+use crate::core::defaults::DefaultsMode;
+use crate::core::duplicates::DuplicatesPolicy;
+use crate::core::grouping::GroupBy;
+use crate::core::mapping::{Connection, Mapping};
+use crate::core::naming::{GroupNamePolicy, Naming, Template};
+use crate::core::options::{Options, Order};
+use crate::core::paths::{Directories, PathsMode};
+use crate::core::proxy_jump::ProxyJumpMode;
+use crate::core::rules::Rules;
+use crate::core::ssh_config::Field;
+use crate::core::variables::{HostVar, ValueType};
 use clap::Parser;
 use clap_verbosity_flag::{Verbosity, WarnLevel};
 use derive_more::FromStr;
+use regex::Regex;
+use std::env;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
@@ -15,15 +28,111 @@ pub struct Arguments {
     #[clap(flatten)]
     pub verbose: Verbosity<WarnLevel>,
 
-    /// Name of the environment to generate.
+    /// Name of the environment to generate, or slash-separated path of nested groups, e.g. prod/eu/web, rooted under all. Hosts within sections of the SSH configuration belong to their section's environment instead
     #[arg(short, long, default_value_t = String::from("local"))]
     pub environment: String,
 
-    /// Ansible variables to add to the hosts, as colon-separated name:value pair, e.g., --var new_ssh_port:22222 --var swap_size:3G
+    /// Ansible variables to add to the hosts, as colon-separated name:value pair, e.g., --var new_ssh_port:22222 --var swap_size:3G, optionally scoped to a group of the environment's path, e.g., --var prod/eu:region:eu-west-1, or --var prod/:tier:1 for its top-level group
     // #[arg(long = "var", value_parser = parse_key_val::<String, String>)]
     #[arg(long = "var", value_parser = parse_key_value)]
     pub vars: Option<Vec<(String, ValueType)>>,
 
+    /// Ansible variable to add to the hosts whose alias matches the glob pattern, as colon-separated pattern:name:value triple, e.g., --host-var db1:postgres_role:primary or --host-var 'web*:weight:10'
+    #[arg(long = "host-var", value_name = "PATTERN:NAME:VALUE")]
+    pub host_vars: Vec<HostVar>,
+
+    /// How to translate ProxyJump: as-is, or resolved against the other hosts of the SSH configuration into a self-contained ProxyCommand or inline user@host:port chain
+    #[arg(long, value_enum, default_value_t = ProxyJumpMode::AsIs)]
+    pub proxy_jump: ProxyJumpMode,
+
+    /// Let Ansible template values of the SSH configuration containing Jinja delimiters, instead of marking them as !unsafe
+    #[arg(long)]
+    pub allow_templating: bool,
+
+    /// Template of the hosts' names in the inventory, over {alias}, {environment} and SSH options, e.g. {alias}.{environment} or {HostName}
+    #[arg(long, default_value = "{alias}")]
+    pub host_name: Template,
+
+    /// Regular expression and replacement rewriting the hosts' names, e.g. --host-name-rewrite '^vagrant-' ''
+    #[arg(long, num_args = 2, value_names = ["REGEX", "REPLACEMENT"])]
+    pub host_name_rewrite: Option<Vec<String>>,
+
+    /// Order of groups, hosts, group vars and SSH options in the inventory: sorted, or as per the SSH configuration and the command line
+    #[arg(long, value_enum, default_value_t = Order::Sorted)]
+    pub order: Order,
+
+    /// Compact consecutively numbered hosts with the same variables, e.g. web01 to web40, into Ansible range patterns, e.g. web[01:40], templating ansible_host if it follows the same numbering
+    #[arg(long)]
+    pub compact_ranges: bool,
+
+    /// Group hosts by the value of their alias or of a SSH option, optionally transformed via domain or split('SEPARATOR')[INDEX], in child groups of the environment named PREFIX_VALUE, e.g. --group-by User, --group-by HostName:domain or --group-by role=alias:split('-')[0]
+    #[arg(long, value_name = "[PREFIX=]KEY[:TRANSFORM]")]
+    pub group_by: Vec<GroupBy>,
+
+    /// What to do with group names, e.g. the environment, breaking Ansible's naming rules: reject them, sanitise them, or allow them with a warning
+    #[arg(long, value_enum, default_value_t = GroupNamePolicy::Allow)]
+    pub group_name_policy: GroupNamePolicy,
+
+    /// What to do with hosts connecting to the same HostName and Port as a previous host: keep them all, keep the first one only, or merge them into the first one, recording their aliases in its ssh_aliases variable
+    #[arg(long, value_enum, default_value_t = DuplicatesPolicy::KeepAll)]
+    pub duplicates: DuplicatesPolicy,
+
+    /// Only consider hosts connecting as the same User as duplicates
+    #[arg(long)]
+    pub duplicates_by_user: bool,
+
+    /// Set each host's description variable to the free-text comment right above its Host line, if any
+    #[arg(long)]
+    pub describe_hosts: bool,
+
+    /// Ansible connection plugin to target, paramiko having its own ansible_paramiko_* variables and ignoring ssh arguments
+    #[arg(long, value_enum, default_value_t = Connection::Ssh)]
+    pub connection: Connection,
+
+    /// Path of a YAML file mapping SSH options to Ansible variables, on top of the built-in mapping
+    #[arg(long)]
+    pub mapping_filepath: Option<PathBuf>,
+
+    /// Path of a YAML file of rules, applied to each host in order, adding the hosts meeting their conditions on alias, SSH options, regex, CIDR of HostName or presence of an option to groups, setting their vars, and removing or overriding their SSH options
+    #[arg(long)]
+    pub rules_filepath: Option<PathBuf>,
+
+    /// Explain which rules fired for each host on stderr
+    #[arg(long, requires = "rules_filepath")]
+    pub explain_rules: bool,
+
+    /// Emit SSH options as a structured ssh_options host variable, assembled into ansible_ssh_extra_args at runtime, so that they can be overridden individually
+    #[arg(long)]
+    pub options_as_vars: bool,
+
+    /// Hoist the variables shared by the hosts into the group's vars, hosts with another value overriding it
+    #[arg(long)]
+    pub hoist: bool,
+
+    /// Fraction of the hosts which must share the value of a variable for --hoist to hoist it, between 0 and 1
+    #[arg(long, requires = "hoist", default_value_t = 1.0, value_parser = parse_fraction)]
+    pub hoist_threshold: f64,
+
+    /// Write the SSH configuration, normalised, to a sidecar ssh_config file next to the output inventory, which ssh then reads via -F, instead of translating SSH options into Ansible variables
+    #[arg(long, requires = "output_filepath")]
+    pub sidecar: bool,
+
+    /// Connect to loopback hosts on port 22, without forwarding, proxying or another user than the current one, locally via ansible_connection: local, rather than via SSH
+    #[arg(long)]
+    pub detect_local: bool,
+
+    /// How to emit values equal to OpenSSH's or Ansible's defaults, e.g. port 22: as-is, omitted when minimal, or filled in for every host when explicit
+    #[arg(long, value_enum, default_value_t = DefaultsMode::AsIs)]
+    pub defaults: DefaultsMode,
+
+    /// How to rewrite the paths of IdentityFile, CertificateFile, UserKnownHostsFile, ControlPath and IdentityAgent: as-is, absolute, relative to the output inventory's directory, or relative to the home directory
+    #[arg(long, value_enum, default_value_t = PathsMode::AsIs)]
+    pub paths: PathsMode,
+
+    /// SSH option to forward as-is, even though it is known to break Ansible, e.g. --keep-option LocalForward
+    #[arg(long = "keep-option", value_parser = parse_field)]
+    pub keep_options: Vec<Field>,
+
     /// Path of the input SSH configuration to parse [default: stdin]
     #[arg(short, long)]
     pub input_filepath: Option<PathBuf>,
@@ -33,16 +142,39 @@ pub struct Arguments {
     pub output_filepath: Option<PathBuf>,
 }
 
-/// Parse a single key-value pair into a (`String`, `ValueType`) pair.
+/// Parse a single key-value pair into a (`String`, `ValueType`) pair. Pairs scoped to a group,
+/// e.g. `prod/eu:region:eu-west-1`, are keyed by this group's path, e.g. `prod/eu/region`.
 fn parse_key_value(s: &str) -> Result<(String, ValueType), Box<dyn Error + Send + Sync + 'static>> {
     let pos = s
         .find(':')
         .ok_or_else(|| format!("invalid key:value pair: no `:` found in `{s}`"))?;
     let key = s[..pos].to_string();
+    if key.contains('/') {
+        let (name, value) = parse_key_value(&s[pos + 1..])?;
+        if name.contains('/') {
+            return Err(format!("invalid variable name: `{name}`").into());
+        }
+        return Ok((format!("{}/{name}", key.trim_end_matches('/')), value));
+    }
     let value = ValueType::from_str(&s[pos + 1..])?;
     Ok((key, value))
 }
 
+/// Parse a SSH option, which is case-insensitive.
+fn parse_field(s: &str) -> Result<Field, Box<dyn Error + Send + Sync + 'static>> {
+    Field::from_key(s).ok_or_else(|| format!("unknown SSH option: `{s}`").into())
+}
+
+/// Parse a fraction, greater than 0 and at most 1.
+fn parse_fraction(s: &str) -> Result<f64, Box<dyn Error + Send + Sync + 'static>> {
+    let fraction = s.parse::<f64>()?;
+    if fraction > 0.0 && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err(format!("not greater than 0 and at most 1: `{s}`").into())
+    }
+}
+
 impl Arguments {
     pub fn validate(&self) -> Result<(), AppError> {
         if let Some(input_filepath) = &self.input_filepath {
@@ -60,6 +192,24 @@ impl Arguments {
                 });
             }
         }
+        if let Some(mapping_filepath) = &self.mapping_filepath {
+            if !mapping_filepath.is_file() {
+                return Err(AppError::InvalidInput {
+                    arg: "--mapping-filepath",
+                    reason: "the provided mapping filepath does not exist or is not a file"
+                        .to_string(),
+                });
+            }
+        }
+        if let Some(rules_filepath) = &self.rules_filepath {
+            if !rules_filepath.is_file() {
+                return Err(AppError::InvalidInput {
+                    arg: "--rules-filepath",
+                    reason: "the provided rules filepath does not exist or is not a file"
+                        .to_string(),
+                });
+            }
+        }
         if let Some(output_filepath) = &self.output_filepath {
             if output_filepath.exists() {
                 if output_filepath.is_file() {
@@ -75,10 +225,97 @@ impl Arguments {
                 }
             }
         }
+        if self.paths == PathsMode::RelativeToInventory && self.output_filepath.is_none() {
+            return Err(AppError::InvalidInput {
+                arg: "--paths",
+                reason: "relative-to-inventory requires -o/--output-filepath".to_string(),
+            });
+        }
+        if self.sidecar {
+            if self.connection == Connection::Paramiko {
+                return Err(AppError::InvalidInput {
+                    arg: "--sidecar",
+                    reason: "paramiko does not read any sidecar SSH config".to_string(),
+                });
+            }
+            if self.paths != PathsMode::AsIs {
+                return Err(AppError::InvalidInput {
+                    arg: "--sidecar",
+                    reason: "the sidecar SSH config is written as-is, hence --paths cannot apply"
+                        .to_string(),
+                });
+            }
+            if self.sidecar_filepath() == self.output_filepath {
+                return Err(AppError::InvalidInput {
+                    arg: "--sidecar",
+                    reason: format!("the output filepath cannot be named {SIDECAR_SSH_CONFIG}"),
+                });
+            }
+        }
         debug!("Input successfully validated");
         Ok(())
     }
 
+    pub fn options(&self) -> Result<Options, AppError> {
+        let mapping = if let Some(mapping_filepath) = &self.mapping_filepath {
+            let mapping_file = File::open(mapping_filepath)?;
+            debug!("Opened mapping file: {:?}", mapping_filepath);
+            Mapping::load(self.connection, BufReader::new(mapping_file))?
+        } else {
+            Mapping::builtin(self.connection)
+        };
+        let rules = if let Some(rules_filepath) = &self.rules_filepath {
+            let rules_file = File::open(rules_filepath)?;
+            debug!("Opened rules file: {:?}", rules_filepath);
+            Some(Rules::load(BufReader::new(rules_file))?)
+        } else {
+            None
+        };
+        let rewrite = match self.host_name_rewrite.as_deref() {
+            Some([regex, replacement]) => Some((
+                Regex::new(regex).map_err(|e| AppError::InvalidInput {
+                    arg: "--host-name-rewrite",
+                    reason: e.to_string(),
+                })?,
+                replacement.to_owned(),
+            )),
+            _ => None,
+        };
+        Ok(Options {
+            proxy_jump: self.proxy_jump,
+            allow_templating: self.allow_templating,
+            connection: self.connection,
+            mapping,
+            rules,
+            explain_rules: self.explain_rules,
+            options_as_vars: self.options_as_vars,
+            hoist_threshold: self.hoist.then_some(self.hoist_threshold),
+            naming: Naming {
+                template: self.host_name.clone(),
+                rewrite,
+            },
+            order: self.order,
+            compact_ranges: self.compact_ranges,
+            group_by: self.group_by.clone(),
+            group_name_policy: self.group_name_policy,
+            duplicates: self.duplicates,
+            duplicates_by_user: self.duplicates_by_user,
+            host_vars: self.host_vars.clone(),
+            describe_hosts: self.describe_hosts,
+            sidecar: self.sidecar,
+            detect_local: self.detect_local,
+            defaults: self.defaults,
+            local_user: env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+            paths: self.paths,
+            directories: Directories::new(
+                env::current_dir()?,
+                env::var_os("HOME").map(PathBuf::from),
+                self.output_filepath.as_deref(),
+            ),
+            keep_options: self.keep_options.iter().copied().collect(),
+        })
+    }
+
     pub fn input(&self) -> Result<impl BufRead, AppError> {
         let input: Box<dyn BufRead> = if let Some(input_filepath) = &self.input_filepath {
             let input_file = File::open(input_filepath)?;
@@ -90,6 +327,24 @@ impl Arguments {
         Ok(input)
     }
 
+    /// Path of the sidecar SSH config, next to the output inventory.
+    fn sidecar_filepath(&self) -> Option<PathBuf> {
+        self.output_filepath
+            .as_ref()
+            .filter(|_| self.sidecar)
+            .map(|output_filepath| output_filepath.with_file_name(SIDECAR_SSH_CONFIG))
+    }
+
+    pub fn sidecar(&self) -> Result<Option<Box<dyn Write>>, AppError> {
+        if let Some(sidecar_filepath) = self.sidecar_filepath() {
+            let sidecar_file = File::create(&sidecar_filepath)?;
+            debug!("Created sidecar SSH config file: {:?}", sidecar_filepath);
+            Ok(Some(Box::new(BufWriter::new(sidecar_file))))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn output(&self) -> Result<impl Write, AppError> {
         let output: Box<dyn Write> = if let Some(output_filepath) = &self.output_filepath {
             let output_file = File::create(output_filepath)?;
@@ -109,6 +364,7 @@ mod tests {
     use crate::common::testing::utilities::{
         read_file, sample_ansible_inventory, temp_file, temp_filepath, SAMPLE_SSH_CONFIG,
     };
+    use crate::core::ssh_config::Field;
     use crate::core::variables::ValueType;
     use clap::Parser;
     use std::fs;
@@ -162,6 +418,29 @@ mod tests {
         assert_eq!(value5, ValueType::String("22".to_string()));
     }
 
+    #[test]
+    fn validate_scoped_vars() {
+        let args = Arguments::parse_from([
+            "",
+            "--var",
+            "prod/eu:region:eu-west-1",
+            "--var",
+            "prod/:tier:1",
+        ]);
+        let result = args.validate();
+        assert!(result.is_ok());
+        assert_eq!(
+            args.vars.unwrap(),
+            Vec::from([
+                (
+                    "prod/eu/region".to_string(),
+                    ValueType::String("eu-west-1".to_string())
+                ),
+                ("prod/tier".to_string(), ValueType::Int64(1)),
+            ])
+        );
+    }
+
     #[test]
     fn validate_non_existing_input_file() {
         let args = Arguments::parse_from(["", "-i", "non-existing-ssh-config-file"]);
@@ -180,6 +459,84 @@ mod tests {
         assert_eq!(err.to_string(), "Invalid user input for arg \"-i/--input-filepath\". Reason: the provided input filepath is not a file");
     }
 
+    #[test]
+    fn validate_non_existing_mapping_file() {
+        let args = Arguments::parse_from(["", "--mapping-filepath", "non-existing-mapping-file"]);
+        let result = args.validate();
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert_eq!(err.to_string(), "Invalid user input for arg \"--mapping-filepath\". Reason: the provided mapping filepath does not exist or is not a file");
+    }
+
+    #[test]
+    fn validate_non_existing_rules_file() {
+        let args = Arguments::parse_from(["", "--rules-filepath", "non-existing-rules-file"]);
+        let result = args.validate();
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert_eq!(err.to_string(), "Invalid user input for arg \"--rules-filepath\". Reason: the provided rules filepath does not exist or is not a file");
+    }
+
+    #[test]
+    fn parse_invalid_hoist_threshold() {
+        let result = Arguments::try_parse_from(["", "--hoist", "--hoist-threshold", "1.5"]);
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("invalid value '1.5' for '--hoist-threshold <HOIST_THRESHOLD>': not greater than 0 and at most 1: `1.5`"));
+    }
+
+    #[test]
+    fn parse_keep_options() {
+        let args = Arguments::parse_from([
+            "",
+            "--keep-option",
+            "localforward",
+            "--keep-option",
+            "RemoteCommand",
+        ]);
+        assert_eq!(
+            args.keep_options,
+            vec![Field::LocalForward, Field::RemoteCommand]
+        );
+    }
+
+    #[test]
+    fn parse_unknown_keep_option() {
+        let result = Arguments::try_parse_from(["", "--keep-option", "Unknown"]);
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("invalid value 'Unknown' for '--keep-option <KEEP_OPTIONS>': unknown SSH option: `Unknown`"));
+    }
+
+    #[test]
+    fn validate_paths_relative_to_inventory_without_output_file() {
+        let args = Arguments::parse_from(["", "--paths", "relative-to-inventory"]);
+        let result = args.validate();
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert_eq!(err.to_string(), "Invalid user input for arg \"--paths\". Reason: relative-to-inventory requires -o/--output-filepath");
+    }
+
+    #[test]
+    fn validate_paths_with_sidecar() {
+        let args = Arguments::parse_from([
+            "",
+            "-o",
+            "./target/test/validate_paths_with_sidecar.yaml",
+            "--sidecar",
+            "--paths",
+            "absolute",
+        ]);
+        let result = args.validate();
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert_eq!(err.to_string(), "Invalid user input for arg \"--sidecar\". Reason: the sidecar SSH config is written as-is, hence --paths cannot apply");
+    }
+
     #[test]
     fn validate_valid_output_file() -> Result<(), AppError> {
         fs::create_dir_all("./target/test")?;
@@ -5,6 +5,34 @@ pub enum AppError {
     #[error("Invalid user input for arg \"{arg}\". Reason: {reason}")]
     InvalidInput { arg: &'static str, reason: String },
 
+    #[error("Invalid SSH config for host \"{host}\". Reason: {reason}")]
+    InvalidSshConfig { host: String, reason: String },
+
+    #[error("Unbalanced section marker at line {line} of SSH config. Reason: {reason}")]
+    UnbalancedSection { line: usize, reason: String },
+
+    #[error("Unknown jump host \"{jump_host}\" in ProxyJump of host \"{host}\"")]
+    UnknownJumpHost { host: String, jump_host: String },
+
+    #[error("Cycle in ProxyJump chain of host \"{host}\": {cycle}")]
+    ProxyJumpCycle { host: String, cycle: String },
+
+    #[error("Hosts \"{first}\" and \"{second}\" are both named \"{name}\" in the inventory")]
+    HostNameCollision {
+        name: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("Invalid Ansible group name \"{name}\". Reason: {reason}")]
+    InvalidGroupName { name: String, reason: String },
+
+    #[error("Invalid mapping file: {0}")]
+    InvalidMapping(String),
+
+    #[error("Invalid rules file: {0}")]
+    InvalidRules(String),
+
     #[error("Failed to serialise to YAML: {0}")]
     Yaml(#[from] serde_yaml::Error),
 
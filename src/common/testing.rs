@@ -18,6 +18,50 @@ pub mod utilities {
   PubkeyAcceptedKeyTypes +ssh-rsa
   HostKeyAlgorithms +ssh-rsa"#;
 
+    pub const SAMPLE_PROXY_JUMP_SSH_CONFIG: &str = r#"Host bastion
+  HostName bastion.example.com
+  User jump
+  IdentityFile /path/to/bastion_key
+Host app
+  HostName 10.0.0.1
+  ProxyJump bastion
+  IdentitiesOnly yes"#;
+
+    pub const SAMPLE_INCOMPATIBLE_SSH_CONFIG: &str = r#"Host default
+  RemoteCommand tmux attach
+  RequestTTY force
+  ControlMaster no
+  LocalForward 8080 localhost:80
+  LogLevel FATAL"#;
+
+    pub const SAMPLE_DEFAULTS_SSH_CONFIG: &str = r#"Host web1
+  HostName web1
+  Port 22
+  User alice
+  ConnectTimeout 30"#;
+
+    pub const SAMPLE_VAGRANT_SSH_CONFIG: &str = r#"Host node1
+  HostName 127.0.0.1
+  User vagrant
+  Port 2222
+  IdentityFile /path/to/private_key
+  IdentitiesOnly yes
+  LogLevel FATAL
+Host node2
+  HostName 127.0.0.1
+  User vagrant
+  Port 2223
+  IdentityFile /path/to/private_key
+  IdentitiesOnly yes
+  LogLevel FATAL
+Host node3
+  HostName 127.0.0.1
+  User vagrant
+  Port 2224
+  IdentityFile /path/to/other_key
+  IdentitiesOnly yes
+  LogLevel FATAL"#;
+
     pub fn sample_ansible_inventory(environment: &str) -> String {
         format!(
             r#"{environment}:
@@ -27,7 +71,9 @@ pub mod utilities {
       ansible_port: 50022
       ansible_user: vagrant
       ansible_ssh_private_key_file: /path/to/private_key
-      ansible_ssh_extra_args: -o HostKeyAlgorithms=+ssh-rsa -o IdentitiesOnly=yes -o LogLevel=FATAL -o PasswordAuthentication=no -o PubkeyAcceptedKeyTypes=+ssh-rsa -o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null
+      ansible_host_key_checking: false
+      ansible_ssh_args: -C -o ControlMaster=auto -o ControlPersist=60s -o PasswordAuthentication=no
+      ansible_ssh_extra_args: -o HostKeyAlgorithms=+ssh-rsa -o IdentitiesOnly=yes -o LogLevel=FATAL -o PubkeyAcceptedKeyTypes=+ssh-rsa -o UserKnownHostsFile=/dev/null
 "#
         )
     }
@@ -41,7 +87,9 @@ pub mod utilities {
       ansible_port: 50022
       ansible_user: vagrant
       ansible_ssh_private_key_file: /path/to/private_key
-      ansible_ssh_extra_args: -o HostKeyAlgorithms=+ssh-rsa -o IdentitiesOnly=yes -o LogLevel=FATAL -o PasswordAuthentication=no -o PubkeyAcceptedKeyTypes=+ssh-rsa -o StrictHostKeyChecking=no -o UserKnownHostsFile=/dev/null
+      ansible_host_key_checking: false
+      ansible_ssh_args: -C -o ControlMaster=auto -o ControlPersist=60s -o PasswordAuthentication=no
+      ansible_ssh_extra_args: -o HostKeyAlgorithms=+ssh-rsa -o IdentitiesOnly=yes -o LogLevel=FATAL -o PubkeyAcceptedKeyTypes=+ssh-rsa -o UserKnownHostsFile=/dev/null
   vars:
     become: true
     http_port: '8080'
@@ -15,6 +15,7 @@ pub fn initialise(args: &Arguments) {
         .json()
         .with_max_level(level)
         .with_thread_ids(true)
+        .with_writer(std::io::stderr) // Keep stdout for the generated Ansible inventory.
         .finish();
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 
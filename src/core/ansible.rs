@@ -1,128 +1,935 @@
+use crate::common::error::AppError;
+use crate::core::compatibility;
+use crate::core::defaults::{self, DefaultsMode, ANSIBLE_SSH_ARGS};
+use crate::core::duplicates;
+use crate::core::grouping;
+use crate::core::mapping::{Connection, Placement};
+use crate::core::naming;
+use crate::core::options::{Options, Order};
+use crate::core::paths;
+use crate::core::proxy_jump::{self, ProxyJumpMode, Resolver};
+use crate::core::quoting;
+use crate::core::ranges;
 use crate::core::ssh_config::{Field, SshConfig};
-use crate::core::variables::ValueType;
-use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use tracing::debug;
+use crate::core::variables::{self, ValueType};
+use indexmap::map::Entry;
+use indexmap::IndexMap;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::iter;
+use std::net::IpAddr;
+use tracing::{debug, warn};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Inventory {
     #[serde(flatten)]
-    groups: BTreeMap<String, Hosts>,
+    groups: IndexMap<String, Hosts>,
+    /// The SSH configurations of the hosts in this inventory, i.e. without the duplicates left out.
+    #[serde(skip)]
+    ssh_configs: Vec<SshConfig>,
 }
 
+/// Ansible's implicit group, which every other group belongs to.
+const ALL: &str = "all";
+
 impl Inventory {
     pub fn new(
         name: &str,
         ssh_configs: &[SshConfig],
         vars: &Option<Vec<(String, ValueType)>>,
-    ) -> Inventory {
-        Inventory {
-            groups: BTreeMap::from([(name.to_owned(), Hosts::new(ssh_configs, vars))]),
+        options: &Options,
+    ) -> Result<Inventory, AppError> {
+        // Duplicates are detected across the whole SSH configuration, before it is split into
+        // environments:
+        let (kept, merged) =
+            duplicates::deduplicate(ssh_configs, options.duplicates, options.duplicates_by_user);
+        // Hosts within a section of the SSH configuration belong to this section's environment,
+        // the others to the provided one:
+        let mut environments = IndexMap::<&str, Vec<SshConfig>>::new();
+        for ssh_config in &kept {
+            let environment = ssh_config.annotations.environment.as_deref();
+            environments
+                .entry(environment.unwrap_or(name))
+                .or_default()
+                .push(ssh_config.clone());
+        }
+        if environments.is_empty() {
+            environments.insert(name, Vec::new());
+        }
+        if options.sidecar && environments.len() > 1 {
+            return Err(AppError::InvalidInput {
+                arg: "--sidecar",
+                reason: format!(
+                    "a single sidecar SSH config cannot hold the hosts of environments `{}`",
+                    environments
+                        .keys()
+                        .copied()
+                        .collect::<Vec<&str>>()
+                        .join("` and `")
+                ),
+            });
+        }
+        // Each environment may be a path of nested groups, e.g. `prod/eu/web`:
+        let paths = environments
+            .keys()
+            .map(|environment| {
+                let path = environment.split('/').collect::<Vec<&str>>();
+                if path.iter().any(|group| group.is_empty()) {
+                    return Err(AppError::InvalidInput {
+                        arg: "--environment",
+                        reason: format!("empty group in `{environment}`"),
+                    });
+                }
+                Ok(path)
+            })
+            .collect::<Result<Vec<Vec<&str>>, AppError>>()?;
+        for (key, _) in vars.iter().flatten() {
+            if let Some((scope, _)) = key.rsplit_once('/') {
+                let scope = scope.split('/').collect::<Vec<&str>>();
+                if !paths.iter().any(|path| path.starts_with(&scope)) {
+                    return Err(AppError::InvalidInput {
+                        arg: "--var",
+                        reason: format!(
+                            "`{}` is not a group of environment `{}`",
+                            scope.join("/"),
+                            environments
+                                .keys()
+                                .copied()
+                                .collect::<Vec<&str>>()
+                                .join("` or `")
+                        ),
+                    });
+                }
+            }
+        }
+        for host_var in &options.host_vars {
+            if !ssh_configs
+                .iter()
+                .any(|ssh_config| host_var.matches(&ssh_config.host))
+            {
+                warn!(
+                    pattern = host_var.pattern,
+                    var = host_var.name,
+                    "Skipped host var matching no host"
+                );
+            }
+        }
+        let mut groups = IndexMap::<String, Hosts>::new();
+        for (path, hosts) in paths.iter().zip(environments.values()) {
+            let (name, group) =
+                Inventory::environment(path, hosts, &merged, ssh_configs, vars, options)?;
+            // Environments may share their top-level group, e.g. `prod/eu` and `prod/us`:
+            match groups.entry(name) {
+                Entry::Occupied(entry) => entry.into_mut().merge(group, options.order),
+                Entry::Vacant(entry) => {
+                    entry.insert(group);
+                }
+            }
+        }
+        if options.order == Order::Sorted {
+            groups.sort_keys();
+        }
+        if paths.iter().any(|path| path.len() > 1) {
+            // Root nested groups explicitly, for Ansible to merge them with other inventories:
+            groups = IndexMap::from([(
+                ALL.to_string(),
+                Hosts {
+                    hosts: None,
+                    children: groups,
+                    vars: None,
+                },
+            )]);
+        }
+        Ok(Inventory {
+            groups,
+            ssh_configs: kept,
+        })
+    }
+
+    /// The SSH configurations of the hosts in this inventory, e.g. to write the sidecar SSH config.
+    pub fn ssh_configs(&self) -> &[SshConfig] {
+        &self.ssh_configs
+    }
+
+    /// Name and build the top-level group of the environment at the provided path, nesting the
+    /// group holding the provided hosts, and the aliases merged into them, within the path's
+    /// parent groups, if any. Unscoped vars apply to the environment's group, and scoped vars to
+    /// the path's groups they are scoped to, if any.
+    fn environment(
+        path: &[&str],
+        hosts: &[SshConfig],
+        merged: &BTreeMap<String, Vec<String>>,
+        ssh_configs: &[SshConfig],
+        vars: &Option<Vec<(String, ValueType)>>,
+        options: &Options,
+    ) -> Result<(String, Hosts), AppError> {
+        let mut scoped_vars = vec![Vec::new(); path.len()];
+        for (key, value) in vars.iter().flatten() {
+            let (depth, key) = match key.rsplit_once('/') {
+                Some((scope, key)) => {
+                    let scope = scope.split('/').collect::<Vec<&str>>();
+                    if !path.starts_with(&scope) {
+                        continue; // Scoped to another environment.
+                    }
+                    (scope.len() - 1, key)
+                }
+                None => (path.len() - 1, key.as_str()),
+            };
+            scoped_vars[depth].push((key.to_owned(), value.clone()));
         }
+        let environment = path[path.len() - 1];
+        let mut group = Hosts::new(
+            environment,
+            hosts,
+            merged,
+            ssh_configs,
+            &scoped_vars.pop(),
+            options,
+        )?;
+        let mut name = naming::group_name(environment, options.group_name_policy)?;
+        for (parent, vars) in path.iter().zip(scoped_vars).rev() {
+            let mut vars = vars.into_iter().collect::<IndexMap<String, ValueType>>();
+            if options.order == Order::Sorted {
+                vars.sort_keys();
+            }
+            group = Hosts {
+                hosts: None,
+                children: IndexMap::from([(name, group)]),
+                vars: (!vars.is_empty()).then_some(vars),
+            };
+            name = naming::group_name(parent, options.group_name_policy)?;
+        }
+        Ok((name, group))
     }
 }
 
+/// Hosts is an Ansible group: its hosts, child groups, and variables.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Hosts {
-    hosts: BTreeMap<String, HostParams>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    vars: Option<BTreeMap<String, ValueType>>,
+    hosts: Option<IndexMap<String, HostParams>>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    children: IndexMap<String, Hosts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vars: Option<IndexMap<String, ValueType>>,
 }
 
 impl Hosts {
-    pub fn new(ssh_configs: &[SshConfig], vars: &Option<Vec<(String, ValueType)>>) -> Hosts {
-        Hosts {
-            hosts: ssh_configs
+    /// Build the group of the provided environment, holding the provided deduplicated hosts, and
+    /// the aliases merged into them, whose jump hosts are resolved against the provided SSH
+    /// configurations.
+    pub fn new(
+        environment: &str,
+        hosts: &[SshConfig],
+        merged: &BTreeMap<String, Vec<String>>,
+        ssh_configs: &[SshConfig],
+        vars: &Option<Vec<(String, ValueType)>>,
+        options: &Options,
+    ) -> Result<Hosts, AppError> {
+        let mut vars = vars
+            .clone()
+            .map(|vec| vec.into_iter().collect::<IndexMap<String, ValueType>>())
+            .unwrap_or_default();
+        let names = name_hosts(environment, hosts, options)?;
+        let mut groups =
+            grouping::group(hosts, &names, &options.group_by, options.group_name_policy)?;
+        if groups.shift_remove(environment).is_some() {
+            warn!(
+                group = environment,
+                "Skipped group named after the environment, whose group already holds every host"
+            );
+        }
+        if options.sidecar {
+            // ssh reads everything from the sidecar SSH config, which only needs each host's alias:
+            let hosts = hosts
                 .iter()
-                .map(|ssh_config| (ssh_config.host.to_owned(), HostParams::new(ssh_config)))
-                .collect::<BTreeMap<String, HostParams>>(),
-            vars: vars
-                .clone()
-                .map(|vec| vec.into_iter().collect::<BTreeMap<String, ValueType>>()),
+                .zip(names)
+                .map(|(ssh_config, name)| {
+                    let mut host_params = HostParams::default();
+                    host_params.connect_via_alias(ssh_config, &name);
+                    host_params.record_aliases(merged.get(&ssh_config.host));
+                    host_params.annotate(ssh_config, options);
+                    host_params.set_host_vars(ssh_config, merged.get(&ssh_config.host), options);
+                    (name, host_params)
+                })
+                .collect::<IndexMap<String, HostParams>>();
+            vars.entry("ansible_ssh_common_args".to_string())
+                .or_insert_with(|| ValueType::String(SIDECAR_SSH_COMMON_ARGS.to_string()));
+            return Ok(Hosts::finish(hosts, vars, groups, options));
+        }
+        // Jump hosts are resolved against every host, including duplicates left out and hosts of
+        // other environments:
+        let mut hosts = hosts
+            .iter()
+            .zip(names)
+            .map(|(ssh_config, name)| {
+                let mut host_params = HostParams::new(ssh_config, ssh_configs, options)?;
+                host_params.connect_via_alias(ssh_config, &name);
+                host_params.record_aliases(merged.get(&ssh_config.host));
+                host_params.annotate(ssh_config, options);
+                host_params.set_host_vars(ssh_config, merged.get(&ssh_config.host), options);
+                Ok((name, host_params))
+            })
+            .collect::<Result<IndexMap<String, HostParams>, AppError>>()?;
+        if hosts
+            .values()
+            .any(|host| host.vars.contains_key(SSH_OPTIONS))
+        {
+            // Let vars provided by the user take precedence:
+            vars.entry("ansible_ssh_extra_args".to_string())
+                .or_insert_with(|| ValueType::String(SSH_OPTIONS_AS_EXTRA_ARGS.to_string()));
+        }
+        if options.connection == Connection::Paramiko {
+            vars.entry("ansible_connection".to_string())
+                .or_insert_with(|| ValueType::String("paramiko".to_string()));
+        }
+        if let Some(threshold) = options.hoist_threshold {
+            hoist(&mut hosts, &mut vars, threshold);
+        }
+        Ok(Hosts::finish(hosts, vars, groups, options))
+    }
+
+    /// Merge the provided group into this one, in the provided order.
+    fn merge(&mut self, other: Hosts, order: Order) {
+        if let Some(hosts) = other.hosts {
+            let merged = self.hosts.get_or_insert_with(IndexMap::new);
+            merged.extend(hosts);
+            if order == Order::Sorted {
+                merged.sort_keys();
+            }
+        }
+        for (name, child) in other.children {
+            match self.children.entry(name) {
+                Entry::Occupied(entry) => entry.into_mut().merge(child, order),
+                Entry::Vacant(entry) => {
+                    entry.insert(child);
+                }
+            }
+        }
+        if let Some(vars) = other.vars {
+            let merged = self.vars.get_or_insert_with(IndexMap::new);
+            merged.extend(vars);
+            if order == Order::Sorted {
+                merged.sort_keys();
+            }
+        }
+        if order == Order::Sorted {
+            self.children.sort_keys();
+        }
+    }
+
+    /// Compact and order the provided hosts, group vars and child groups, as per the provided
+    /// options.
+    fn finish(
+        hosts: IndexMap<String, HostParams>,
+        mut vars: IndexMap<String, ValueType>,
+        groups: IndexMap<String, Vec<String>>,
+        options: &Options,
+    ) -> Hosts {
+        let mut hosts = compact(hosts, options);
+        let mut children = groups
+            .into_iter()
+            .map(|(group, names)| {
+                let mut hosts = names
+                    .into_iter()
+                    .map(|name| (name, HostParams::default()))
+                    .collect::<IndexMap<String, HostParams>>();
+                if options.order == Order::Sorted {
+                    hosts.sort_keys();
+                }
+                let child = Hosts {
+                    hosts: Some(hosts),
+                    children: IndexMap::new(),
+                    vars: None,
+                };
+                (group, child)
+            })
+            .collect::<IndexMap<String, Hosts>>();
+        if options.order == Order::Sorted {
+            hosts.sort_keys();
+            vars.sort_keys();
+            children.sort_keys();
+        }
+        Hosts {
+            hosts: Some(hosts),
+            children,
+            vars: (!vars.is_empty()).then_some(vars),
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-/// HostParams groups all the Ansible inventory parameters for Ansible to connect to this host.
-/// See also: https://docs.ansible.com/ansible/latest/inventory_guide/intro_inventory.html#connecting-to-hosts-behavioral-inventory-parameters
-pub struct HostParams {
-    /// The name of the host to connect to, if different from the alias you wish to give to it.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ansible_host: Option<String>,
+/// Name each of the provided hosts in the inventory, failing if different hosts get the same
+/// name. Repeated `Host` blocks for the same alias keep being merged, the last one winning.
+fn name_hosts(
+    environment: &str,
+    ssh_configs: &[SshConfig],
+    options: &Options,
+) -> Result<Vec<String>, AppError> {
+    let mut aliases = BTreeMap::<String, &str>::new();
+    let mut names = Vec::with_capacity(ssh_configs.len());
+    for ssh_config in ssh_configs {
+        let name = options.naming.name(ssh_config, environment)?;
+        naming::check_host_name(&name);
+        if let Some(alias) = aliases.insert(name.to_owned(), &ssh_config.host) {
+            if alias != ssh_config.host {
+                return Err(AppError::HostNameCollision {
+                    name,
+                    first: alias.to_owned(),
+                    second: ssh_config.host.to_owned(),
+                });
+            }
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
 
-    /// The connection port number, if not the default (22 for ssh)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ansible_port: Option<u16>,
+/// Move each variable defined by every host into the group's vars, if its most common value is
+/// shared by at least the provided fraction of the hosts. Hosts with another value keep it as an
+/// override, so that every host still resolves to the same value. Variables already set on the
+/// group, e.g. via `--var`, are left as-is.
+fn hoist(
+    hosts: &mut IndexMap<String, HostParams>,
+    vars: &mut IndexMap<String, ValueType>,
+    threshold: f64,
+) {
+    let Some(first) = hosts.values().next() else {
+        return;
+    };
+    let names = first
+        .vars
+        .keys()
+        .filter(|name| !vars.contains_key(*name))
+        .filter(|name| hosts.values().all(|host| host.vars.contains_key(*name)))
+        .cloned()
+        .collect::<Vec<String>>();
+    for name in names {
+        // ValueType is not hashable, because of floats, hence counting values in a list:
+        let mut counts: Vec<(&ValueType, usize)> = Vec::new();
+        for host in hosts.values() {
+            let value = &host.vars[&name];
+            match counts.iter_mut().find(|(other, _)| *other == value) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((value, 1)),
+            }
+        }
+        // The most common value, or the first one seen in case of a tie:
+        let (value, count) = counts
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .expect("at least one host");
+        if count < 2 || (count as f64) < threshold * hosts.len() as f64 {
+            continue;
+        }
+        let value = value.clone();
+        for host in hosts.values_mut() {
+            if host.vars[&name] == value {
+                host.vars.remove(&name);
+            }
+        }
+        debug!(name, count, "Hoisted variable into the group's vars");
+        vars.insert(name, value);
+    }
+}
 
-    /// The user name to use when connecting to the host
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ansible_user: Option<String>,
+/// Compact numbered hosts into range patterns, if requested.
+fn compact(hosts: IndexMap<String, HostParams>, options: &Options) -> IndexMap<String, HostParams> {
+    if !options.compact_ranges {
+        return hosts;
+    }
+    ranges::compact(
+        hosts
+            .into_iter()
+            .map(|(name, host)| (name, host.vars))
+            .collect(),
+    )
+    .into_iter()
+    .map(|(name, vars)| (name, HostParams { vars }))
+    .collect()
+}
 
-    /// Private key file used by SSH. Useful if using multiple keys and you do not want to use SSH agent.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ansible_ssh_private_key_file: Option<String>,
+/// File name of the sidecar SSH config, written next to the Ansible inventory.
+pub const SIDECAR_SSH_CONFIG: &str = "ssh_config";
 
-    /// This setting is always appended to the default command line for sftp, scp, and ssh. Useful to configure a ProxyCommand for a certain host (or group).
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ansible_ssh_common_args: Option<String>,
+/// Group-level `ansible_ssh_common_args` pointing ssh to the sidecar SSH config.
+const SIDECAR_SSH_COMMON_ARGS: &str = "-F {{ inventory_dir | quote }}/ssh_config";
 
-    /// This setting is always appended to the default ssh command line.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ansible_ssh_extra_args: Option<String>,
+/// Host variable holding the aliases of the duplicates merged into a host.
+const SSH_ALIASES: &str = "ssh_aliases";
+
+/// Host variable holding the comment right above a host's `Host` line, when describing hosts.
+const DESCRIPTION: &str = "description";
+
+/// Host variable holding SSH options, when emitted as structured variables.
+const SSH_OPTIONS: &str = "ssh_options";
+
+/// Group-level Jinja expression assembling `ssh_options`, overridden by the optional
+/// `ssh_options_overrides`, into `ansible_ssh_extra_args` at runtime. Ansible reads YAML 1.1,
+/// where `yes` and `no` are booleans, hence turning these back into strings.
+const SSH_OPTIONS_AS_EXTRA_ARGS: &str = "{% for option, value in (ssh_options | default({}) | combine(ssh_options_overrides | default({}))).items() %}-o {{ (option ~ '=' ~ ((value | ternary('yes', 'no')) if value is boolean else value)) | quote }}{% if not loop.last %} {% endif %}{% endfor %}";
+
+/// Well-known Ansible connection variables, which come first and in this order when set. Other
+/// variables follow, in alphabetical order. See also:
+/// https://docs.ansible.com/ansible/latest/inventory_guide/intro_inventory.html#connecting-to-hosts-behavioral-inventory-parameters
+const VARIABLES_ORDER: [&str; 12] = [
+    // The connection type to the host, e.g. local to run on the control node.
+    "ansible_connection",
+    // The name of the host to connect to, if different from the alias you wish to give to it.
+    "ansible_host",
+    // The connection port number, if not the default (22 for ssh)
+    "ansible_port",
+    // The user name to use when connecting to the host
+    "ansible_user",
+    // Private key file used by SSH. Useful if using multiple keys and you do not want to use SSH agent.
+    "ansible_ssh_private_key_file",
+    // Whether to check the host key of the host.
+    "ansible_host_key_checking",
+    // The connection timeout, in seconds.
+    "ansible_ssh_timeout",
+    // The number of attempts to connect after the first one.
+    "ansible_ssh_retries",
+    // The path of the control socket used for connection sharing.
+    "ansible_control_path",
+    // This setting replaces Ansible's default ssh arguments, and takes precedence over the options Ansible sets itself, e.g. for authentication.
+    "ansible_ssh_args",
+    // This setting is always appended to the default command line for sftp, scp, and ssh. Useful to configure a ProxyCommand for a certain host (or group).
+    "ansible_ssh_common_args",
+    // This setting is always appended to the default ssh command line.
+    "ansible_ssh_extra_args",
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+/// HostParams groups all the Ansible inventory parameters for Ansible to connect to this host,
+/// as mapped from its SSH configuration.
+pub struct HostParams {
+    vars: BTreeMap<String, ValueType>,
+}
+
+/// SSH options placed as `-o` arguments, per Ansible variable.
+#[derive(Default)]
+struct Args {
+    ssh: Vec<(Field, String)>,
+    common: Vec<(Field, String)>,
+    extra: Vec<(Field, String)>,
 }
 
 impl HostParams {
-    pub fn new(ssh_config: &SshConfig) -> HostParams {
+    pub fn new(
+        ssh_config: &SshConfig,
+        ssh_configs: &[SshConfig],
+        options: &Options,
+    ) -> Result<HostParams, AppError> {
         debug!("Provided SSH config: {:?}", ssh_config);
+        if options.detect_local && is_local(ssh_config, options.local_user.as_deref()) {
+            debug!(host = ssh_config.host, "Detected local host");
+            return Ok(HostParams {
+                vars: BTreeMap::from([(
+                    "ansible_connection".to_string(),
+                    ValueType::String("local".to_string()),
+                )]),
+            });
+        }
         let mut fields = ssh_config.fields.clone();
-        let ansible_host = fields.remove(&Field::HostName);
-        let ansible_port = fields
-            .remove(&Field::Port)
-            .map(|s| s.parse::<u16>().expect("an integer between 0 and 65535"));
-        let ansible_user = fields.remove(&Field::User);
-        let ansible_ssh_private_key_file = fields.remove(&Field::IdentityFile);
-        let ansible_ssh_common_args = fields.remove(&Field::ProxyCommand);
-        let ansible_ssh_extra_args = if fields.is_empty() {
-            None
-        } else {
-            Some(
-                fields
-                    .into_iter()
-                    .map(|(k, v)| format!("-o {k}={v}"))
-                    .collect::<Vec<String>>()
-                    .join(" "),
-            )
+        if fields.shift_remove(&Field::Tag).is_some() {
+            debug!(
+                host = ssh_config.host,
+                "Mapped Tag to groups, rather than to ssh arguments"
+            );
+        }
+        if options.order == Order::Sorted {
+            fields.sort_keys();
+        }
+        if options.defaults == DefaultsMode::Minimal {
+            defaults::minimise(&ssh_config.host, &mut fields, options.local_user.as_deref());
+        }
+        let templated = paths::rewrite(
+            &mut fields,
+            options.paths,
+            &options.directories,
+            options.allow_templating,
+        );
+        let text = |field: Field, value: String| {
+            if templated.contains(&field) {
+                ValueType::String(value)
+            } else {
+                ValueType::from_ssh_config(value, options.allow_templating)
+            }
         };
-        HostParams {
-            ansible_host,
-            ansible_port,
-            ansible_user,
-            ansible_ssh_private_key_file,
-            ansible_ssh_common_args,
-            ansible_ssh_extra_args,
+        let mut args = Args::default();
+        if options.proxy_jump != ProxyJumpMode::AsIs {
+            if let Some(hops) = Resolver::new(ssh_configs).resolve(ssh_config)? {
+                if options.proxy_jump == ProxyJumpMode::Inline {
+                    fields.insert(Field::ProxyJump, proxy_jump::inline(&hops));
+                } else if options.connection == Connection::Paramiko {
+                    // Paramiko has a dedicated variable, but ignores ssh arguments:
+                    fields.shift_remove(&Field::ProxyJump);
+                    fields.insert(Field::ProxyCommand, proxy_jump::proxy_command(&hops));
+                } else {
+                    fields.shift_remove(&Field::ProxyJump);
+                    args.common
+                        .push((Field::ProxyCommand, proxy_jump::proxy_command(&hops)));
+                }
+            }
+        }
+        let mut vars = BTreeMap::new();
+        for (field, value) in fields {
+            let mut rule = options.mapping.rule(field);
+            // Paramiko ignores ssh arguments altogether, which are then reported below:
+            if options.connection == Connection::Ssh
+                && matches!(rule.placement, Placement::CommonArgs | Placement::ExtraArgs)
+                && !options.keep_options.contains(&field)
+            {
+                if let Some(incompatibility) = compatibility::check(field, &value) {
+                    warn!(
+                        host = ssh_config.host,
+                        %field,
+                        value,
+                        reason = incompatibility.reason,
+                        "{} SSH option incompatible with Ansible, use --keep-option {field} to keep it",
+                        if incompatibility.rule.placement == Placement::Drop { "Dropped" } else { "Moved" },
+                    );
+                    rule = incompatibility.rule;
+                }
+            }
+            match rule.placement {
+                Placement::HostVar => {
+                    match (
+                        &rule.variable,
+                        rule.transform(ssh_config, field, value.clone(), |value| {
+                            text(field, value)
+                        })?,
+                    ) {
+                        (Some(variable), Some(value)) => {
+                            vars.insert(variable.to_owned(), value);
+                        }
+                        _ => args.extra.push((field, value)),
+                    }
+                }
+                Placement::SshArgs => args.ssh.push((field, value)),
+                Placement::CommonArgs => args.common.push((field, value)),
+                Placement::ExtraArgs => args.extra.push((field, value)),
+                Placement::Drop => debug!(host = ssh_config.host, %field, "Dropped SSH option"),
+            }
+        }
+        if options.connection == Connection::Paramiko {
+            for (field, value) in args.ssh.into_iter().chain(args.common).chain(args.extra) {
+                warn!(
+                    host = ssh_config.host,
+                    %field,
+                    value,
+                    "Dropped SSH option unsupported by paramiko"
+                );
+            }
+        } else {
+            place_args(&mut vars, args, options, &templated);
+        }
+        if options.defaults == DefaultsMode::Explicit {
+            defaults::fill(
+                &ssh_config.host,
+                &mut vars,
+                options.connection,
+                options.local_user.as_deref(),
+            );
+        }
+        Ok(HostParams { vars })
+    }
+
+    /// Connect to the provided host via its alias, like ssh does, if the host is named differently
+    /// in the inventory and neither `HostName` nor a local connection is set.
+    fn connect_via_alias(&mut self, ssh_config: &SshConfig, name: &str) {
+        if name != ssh_config.host && !self.vars.contains_key("ansible_connection") {
+            self.vars
+                .entry("ansible_host".to_string())
+                .or_insert_with(|| ValueType::String(ssh_config.host.to_owned()));
+        }
+    }
+
+    /// Record the aliases of the duplicates merged into this host, if any.
+    fn record_aliases(&mut self, aliases: Option<&Vec<String>>) {
+        if let Some(aliases) = aliases {
+            self.vars.insert(
+                SSH_ALIASES.to_string(),
+                ValueType::List(aliases.iter().cloned().map(ValueType::String).collect()),
+            );
+        }
+    }
+
+    /// Set the variables annotated in the comments of the provided host, overriding the ones
+    /// mapped from its SSH options, and its description, if describing hosts.
+    fn annotate(&mut self, ssh_config: &SshConfig, options: &Options) {
+        let annotations = &ssh_config.annotations;
+        for (key, value) in &annotations.vars {
+            self.vars.insert(key.to_owned(), value.clone());
+        }
+        if let (true, Some(description)) = (options.describe_hosts, &annotations.description) {
+            // Free text, which Ansible must not template either:
+            self.vars.entry(DESCRIPTION.to_string()).or_insert_with(|| {
+                ValueType::from_ssh_config(description.to_owned(), options.allow_templating)
+            });
+        }
+    }
+
+    /// Set the host vars whose pattern matches the alias of the provided host, or of any of the
+    /// duplicates merged into it, overriding any other variable.
+    fn set_host_vars(
+        &mut self,
+        ssh_config: &SshConfig,
+        aliases: Option<&Vec<String>>,
+        options: &Options,
+    ) {
+        for host_var in &options.host_vars {
+            if iter::once(&ssh_config.host)
+                .chain(aliases.into_iter().flatten())
+                .any(|alias| host_var.matches(alias))
+            {
+                self.vars
+                    .insert(host_var.name.to_owned(), host_var.value.clone());
+            }
+        }
+    }
+
+    /// Iterate over the variables, well-known connection variables first.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ValueType)> {
+        let known = VARIABLES_ORDER
+            .iter()
+            .filter_map(|name| self.vars.get_key_value(*name));
+        let others = self
+            .vars
+            .iter()
+            .filter(|(name, _)| !VARIABLES_ORDER.contains(&name.as_str()));
+        known.chain(others)
+    }
+}
+
+impl Serialize for HostParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.vars.len()))?;
+        for (name, value) in self.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Whether the provided SSH configuration targets the control node itself, as the provided local
+/// user, i.e. a loopback address on the default port, without any forwarding or proxying, and
+/// without any other user, which a local connection would not switch to. Loopback addresses on
+/// another port, e.g. Vagrant's forwarded 2222, typically reach another machine.
+fn is_local(ssh_config: &SshConfig, local_user: Option<&str>) -> bool {
+    let fields = &ssh_config.fields;
+    let is_local_user = fields
+        .get(&Field::User)
+        .is_none_or(|user| local_user == Some(user.as_str()));
+    let host_name = ssh_config.host_name();
+    let is_loopback = host_name.eq_ignore_ascii_case("localhost")
+        || host_name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    let is_default_port = fields.get(&Field::Port).is_none_or(|port| port == "22");
+    let is_direct = [Field::ProxyJump, Field::ProxyCommand].iter().all(|field| {
+        fields
+            .get(field)
+            .is_none_or(|value| value.eq_ignore_ascii_case("none"))
+    });
+    let is_forwarding = [
+        Field::LocalForward,
+        Field::RemoteForward,
+        Field::DynamicForward,
+    ]
+    .iter()
+    .any(|field| fields.contains_key(field));
+    is_loopback && is_local_user && is_default_port && is_direct && !is_forwarding
+}
+
+/// Place the provided `-o` arguments in their respective ssh arguments variable, the provided
+/// templated ones being relative to the inventory's directory.
+fn place_args(
+    vars: &mut BTreeMap<String, ValueType>,
+    args: Args,
+    options: &Options,
+    templated: &HashSet<Field>,
+) {
+    if !args.ssh.is_empty() {
+        // Keep Ansible's defaults, unless overridden:
+        let defaults = ANSIBLE_SSH_ARGS
+            .map(|(field, value)| (field, value.to_string()))
+            .to_vec();
+        let mut ssh_args = defaults.clone();
+        for (field, value) in args.ssh {
+            match ssh_args.iter_mut().find(|(k, _)| *k == field) {
+                Some(ssh_arg) => ssh_arg.1 = value,
+                None => ssh_args.push((field, value)),
+            }
+        }
+        if options.defaults != DefaultsMode::Minimal || ssh_args != defaults {
+            let mut ssh_args = to_args(ssh_args, templated);
+            ssh_args.insert(0, ("-C".to_string(), false)); // Compression.
+            append_args(vars, "ansible_ssh_args", ssh_args, options);
+        }
+    }
+    append_args(
+        vars,
+        "ansible_ssh_common_args",
+        to_args(args.common, templated),
+        options,
+    );
+    if options.options_as_vars {
+        if !args.extra.is_empty() {
+            let ssh_options = args
+                .extra
+                .into_iter()
+                .map(|(field, value)| {
+                    let value = if templated.contains(&field) {
+                        ValueType::String(value)
+                    } else {
+                        ValueType::from_ssh_config(value, options.allow_templating)
+                    };
+                    (field.to_string(), value)
+                })
+                .collect::<BTreeMap<String, ValueType>>();
+            vars.insert(SSH_OPTIONS.to_string(), ValueType::Map(ssh_options));
+        }
+    } else {
+        append_args(
+            vars,
+            "ansible_ssh_extra_args",
+            to_args(args.extra, templated),
+            options,
+        );
+    }
+}
+
+/// Convert the provided options into `-o` arguments, each telling whether Ansible must template
+/// it, as per the provided templated options.
+fn to_args(options: Vec<(Field, String)>, templated: &HashSet<Field>) -> Vec<(String, bool)> {
+    options
+        .into_iter()
+        .flat_map(|(k, v)| {
+            let is_templated = templated.contains(&k);
+            // ssh splits values on whitespace, which the inventory's directory may contain, unless
+            // double-quoted, as UserKnownHostsFile's templated paths already are:
+            let v = if is_templated && k != Field::UserKnownHostsFile && !v.starts_with('"') {
+                format!("\"{v}\"")
+            } else {
+                v
+            };
+            [
+                ("-o".to_string(), false),
+                (format!("{k}={v}"), is_templated),
+            ]
+        })
+        .collect()
+}
+
+/// Quote the provided argument, relative to the inventory's directory, so that Ansible templates
+/// it back into a single argument, whatever the inventory's directory.
+fn quote_templated(arg: &str) -> String {
+    arg.split(variables::INVENTORY_DIR)
+        .map(|part| {
+            if part.is_empty() {
+                Cow::Borrowed(part)
+            } else {
+                quoting::quote(part)
+            }
+        })
+        .collect::<Vec<Cow<str>>>()
+        .join(variables::QUOTED_INVENTORY_DIR)
+}
+
+/// Quote and append the provided arguments to the provided variable, e.g. after an option
+/// already placed in `ansible_ssh_common_args` as a host variable. If any argument must be
+/// templated, the Jinja delimiters of the others are escaped, each within its own quotes.
+fn append_args(
+    vars: &mut BTreeMap<String, ValueType>,
+    name: &str,
+    args: Vec<(String, bool)>,
+    options: &Options,
+) {
+    if args.is_empty() {
+        return;
+    }
+    let is_templated = args.iter().any(|(_, templated)| *templated);
+    let escape = |arg: &str| {
+        if is_templated && !options.allow_templating {
+            variables::escape_jinja(arg).into_owned()
+        } else {
+            arg.to_owned()
         }
+    };
+    let mut value = args
+        .iter()
+        .map(|(arg, templated)| {
+            if *templated {
+                quote_templated(arg)
+            } else {
+                escape(&quoting::quote(arg))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    if let Some(existing) = vars.remove(name) {
+        let existing = match existing {
+            ValueType::Unsafe(existing) => escape(&existing),
+            existing => existing.to_string(),
+        };
+        value = format!("{existing} {value}");
     }
+    let value = if is_templated {
+        ValueType::String(value)
+    } else {
+        ValueType::from_ssh_config(value, options.allow_templating)
+    };
+    vars.insert(name.to_owned(), value);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{HostParams, Inventory};
+    use super::{is_local, HostParams, Inventory};
+    use crate::common::error::AppError;
+    use crate::common::testing::utilities::{
+        SAMPLE_DEFAULTS_SSH_CONFIG, SAMPLE_INCOMPATIBLE_SSH_CONFIG, SAMPLE_PROXY_JUMP_SSH_CONFIG,
+        SAMPLE_VAGRANT_SSH_CONFIG,
+    };
     use crate::core::{
-        ssh_config::{Field, SshConfig},
-        variables::ValueType,
+        defaults::DefaultsMode,
+        duplicates::DuplicatesPolicy,
+        grouping::GroupBy,
+        mapping::{Connection, Mapping},
+        naming::{Naming, Template},
+        options::{Options, Order},
+        paths::{Directories, PathsMode},
+        proxy_jump::ProxyJumpMode,
+        ssh_config::{Annotations, Field, SshConfig},
+        variables::{HostVar, ValueType},
     };
+    use indexmap::IndexMap;
+    use proptest::prelude::*;
+    use regex::Regex;
+    use rstest::rstest;
     use serde_yaml;
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
 
     fn sample_host_params() -> HostParams {
         HostParams {
-            ansible_host: Some("127.0.0.1".to_string()),
-            ansible_port: Some(50022u16),
-            ansible_user: Some("vagrant".to_string()),
-            ansible_ssh_private_key_file: Some("/path/to/private_key".to_string()),
-            ansible_ssh_common_args: None,
-            ansible_ssh_extra_args: None,
+            vars: BTreeMap::from([
+                (
+                    "ansible_host".to_string(),
+                    ValueType::String("127.0.0.1".to_string()),
+                ),
+                ("ansible_port".to_string(), ValueType::Int64(50022)),
+                (
+                    "ansible_user".to_string(),
+                    ValueType::String("vagrant".to_string()),
+                ),
+                (
+                    "ansible_ssh_private_key_file".to_string(),
+                    ValueType::String("/path/to/private_key".to_string()),
+                ),
+            ]),
         }
     }
 
@@ -146,11 +953,11 @@ mod tests {
     }
 
     #[test]
-    fn serialize_inventory_to_yaml() -> Result<(), serde_yaml::Error> {
+    fn serialize_inventory_to_yaml() -> Result<(), AppError> {
         // Given:
         let ssh_config = SshConfig {
             host: "default".to_string(),
-            fields: BTreeMap::from([
+            fields: IndexMap::from([
                 (Field::HostName, "127.0.0.1".to_string()),
                 (Field::User, "vagrant".to_string()),
                 (Field::Port, "50022".to_string()),
@@ -158,6 +965,7 @@ mod tests {
                 (Field::StrictHostKeyChecking, "no".to_string()),
                 (Field::PasswordAuthentication, "no".to_string()),
             ]),
+            annotations: Annotations::default(),
         };
         let ssh_configs = Vec::from([ssh_config]);
         let vars = Some(Vec::from([
@@ -169,7 +977,7 @@ mod tests {
             ("num_workers".to_string(), ValueType::Int64(4)),
             ("swap_size".to_string(), ValueType::String("3G".to_string())),
         ]));
-        let inventory = Inventory::new("local", &ssh_configs, &vars);
+        let inventory = Inventory::new("local", &ssh_configs, &vars, &Options::default())?;
 
         // When:
         let yaml = serde_yaml::to_string(&inventory)?;
@@ -184,7 +992,8 @@ mod tests {
       ansible_port: 50022
       ansible_user: vagrant
       ansible_ssh_private_key_file: /path/to/private_key
-      ansible_ssh_extra_args: -o PasswordAuthentication=no -o StrictHostKeyChecking=no
+      ansible_host_key_checking: false
+      ansible_ssh_args: -C -o ControlMaster=auto -o ControlPersist=60s -o PasswordAuthentication=no
   vars:
     become: true
     http_port: '8080'
@@ -194,4 +1003,1281 @@ mod tests {
         );
         Ok(())
     }
+
+    #[rstest]
+    // Given:
+    #[case::as_is(
+        ProxyJumpMode::AsIs,
+        None,
+        "-o IdentitiesOnly=yes -o ProxyJump=bastion"
+    )]
+    #[case::proxy_command(
+        ProxyJumpMode::ProxyCommand,
+        Some("-o 'ProxyCommand=ssh -W %h:%p -i /path/to/bastion_key -l jump bastion.example.com'"),
+        "-o IdentitiesOnly=yes"
+    )]
+    #[case::inline(
+        ProxyJumpMode::Inline,
+        None,
+        "-o IdentitiesOnly=yes -o ProxyJump=jump@bastion.example.com"
+    )]
+    fn new_host_params_with_proxy_jump(
+        #[case] proxy_jump: ProxyJumpMode,
+        #[case] common_args: Option<&str>,
+        #[case] extra_args: &str,
+    ) -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = SshConfig::parse(&mut SAMPLE_PROXY_JUMP_SSH_CONFIG.as_bytes())?;
+        let options = Options {
+            proxy_jump,
+            ..Options::default()
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_configs[1], &ssh_configs, &options)?;
+
+        // Then:
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_common_args"),
+            common_args
+                .map(|common_args| ValueType::String(common_args.to_string()))
+                .as_ref()
+        );
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_extra_args"),
+            Some(&ValueType::String(extra_args.to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_quotes_extra_args() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([
+                (Field::UserKnownHostsFile, "\"/a b/known\"".to_string()),
+                (Field::SetEnv, "FOO=\"x y\"".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_config, &[], &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_extra_args"),
+            Some(&ValueType::String(
+                r#"-o 'SetEnv=FOO="x y"' -o 'UserKnownHostsFile="/a b/known"'"#.to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn shlex_split_of_extra_args_is_intended_argv(
+            values in prop::collection::vec("[^\u{0}]*", 4)
+        ) {
+            // Given:
+            let fields = IndexMap::from([
+                (Field::LogLevel, values[0].to_owned()),
+                (Field::SendEnv, values[1].to_owned()),
+                (Field::SetEnv, values[2].to_owned()),
+                (Field::UserKnownHostsFile, values[3].to_owned()),
+            ]);
+            let expected = fields
+                .iter()
+                .flat_map(|(k, v)| ["-o".to_string(), format!("{k}={v}")])
+                .collect::<Vec<String>>();
+            let ssh_config = SshConfig {
+                host: "default".to_string(),
+                fields,
+                annotations: Annotations::default(),
+            };
+
+            // When:
+            let host_params = HostParams::new(&ssh_config, &[], &Options::default()).unwrap();
+
+            // Then:
+            let extra_args = host_params.vars["ansible_ssh_extra_args"].to_string();
+            prop_assert_eq!(shlex::split(&extra_args), Some(expected));
+        }
+    }
+
+    #[test]
+    fn new_host_params_marks_jinja_as_unsafe() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([
+                (Field::HostName, "127.0.0.1".to_string()),
+                (
+                    Field::ProxyCommand,
+                    "ssh -W %h:%p {{ bastion }}".to_string(),
+                ),
+                (Field::SetEnv, "GREETING={% raw %}".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        };
+        let ssh_configs = Vec::from([ssh_config]);
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    default:
+      ansible_host: 127.0.0.1
+      ansible_ssh_common_args: !unsafe -o 'ProxyCommand=ssh -W %h:%p {{ bastion }}'
+      ansible_ssh_extra_args: !unsafe -o 'SetEnv=GREETING={% raw %}'
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_allowing_templating() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([(
+                Field::ProxyCommand,
+                "ssh -W %h:%p {{ bastion }}".to_string(),
+            )]),
+            annotations: Annotations::default(),
+        };
+        let options = Options {
+            allow_templating: true,
+            ..Options::default()
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_config, &[], &options)?;
+
+        // Then:
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_common_args"),
+            Some(&ValueType::String(
+                "-o 'ProxyCommand=ssh -W %h:%p {{ bastion }}'".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_with_connection_variables() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([
+                (Field::StrictHostKeyChecking, "yes".to_string()),
+                (Field::ConnectTimeout, "30".to_string()),
+                (Field::ConnectionAttempts, "3".to_string()),
+                (Field::ControlPath, "~/.ssh/cm-%C".to_string()),
+                (Field::ControlPersist, "10m".to_string()),
+                (Field::PreferredAuthentications, "publickey".to_string()),
+                (Field::LogLevel, "ERROR".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_config, &[], &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            host_params.vars.get("ansible_host_key_checking"),
+            Some(&ValueType::Bool(true))
+        );
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_timeout"),
+            Some(&ValueType::Int64(30))
+        );
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_retries"),
+            Some(&ValueType::Int64(2))
+        );
+        assert_eq!(
+            host_params.vars.get("ansible_control_path"),
+            Some(&ValueType::String("~/.ssh/cm-%%C".to_string()))
+        );
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_args"),
+            Some(&ValueType::String(
+                "-C -o ControlMaster=auto -o ControlPersist=10m -o PreferredAuthentications=publickey"
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_extra_args"),
+            Some(&ValueType::String("-o LogLevel=ERROR".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_keeps_strict_host_key_checking_accept_new() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([(Field::StrictHostKeyChecking, "accept-new".to_string())]),
+            annotations: Annotations::default(),
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_config, &[], &Options::default())?;
+
+        // Then:
+        assert_eq!(host_params.vars.get("ansible_host_key_checking"), None);
+        assert_eq!(
+            host_params.vars.get("ansible_ssh_extra_args"),
+            Some(&ValueType::String(
+                "-o StrictHostKeyChecking=accept-new".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_with_invalid_connect_timeout() {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([(Field::ConnectTimeout, "soon".to_string())]),
+            annotations: Annotations::default(),
+        };
+
+        // When:
+        let result = HostParams::new(&ssh_config, &[], &Options::default());
+
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Invalid SSH config for host \"default\". Reason: ConnectTimeout is not a non-negative integer: soon"
+        );
+    }
+
+    #[rstest]
+    // Given:
+    #[case::filtered(
+        &[],
+        r#"ansible_ssh_args: -C -o ControlMaster=no -o ControlPersist=60s
+ansible_ssh_extra_args: -o LogLevel=FATAL
+"#
+    )]
+    #[case::kept_on_demand(
+        &[Field::LocalForward, Field::ControlMaster],
+        r#"ansible_ssh_extra_args: -o ControlMaster=no -o 'LocalForward=8080 localhost:80' -o LogLevel=FATAL
+"#
+    )]
+    fn new_host_params_with_options_incompatible_with_ansible(
+        #[case] keep_options: &[Field],
+        #[case] expected: &str,
+    ) -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = SshConfig::parse(&mut SAMPLE_INCOMPATIBLE_SSH_CONFIG.as_bytes())?;
+        let options = Options {
+            keep_options: BTreeSet::from_iter(keep_options.iter().copied()),
+            ..Options::default()
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_configs[0], &[], &options)?;
+
+        // Then:
+        assert_eq!(serde_yaml::to_string(&host_params)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::localhost(&[(Field::HostName, "localhost")], true)]
+    #[case::loopback_ipv4(&[(Field::HostName, "127.0.1.1"), (Field::Port, "22")], true)]
+    #[case::loopback_ipv6(&[(Field::HostName, "::1"), (Field::User, "me")], true)]
+    #[case::loopback_ipv6_other_user(&[(Field::HostName, "::1"), (Field::User, "deploy")], false)]
+    #[case::alias(&[], true)]
+    #[case::proxy_jump_none(&[(Field::HostName, "127.0.0.1"), (Field::ProxyJump, "none")], true)]
+    #[case::remote(&[(Field::HostName, "192.168.0.1")], false)]
+    #[case::forwarded_port(&[(Field::HostName, "127.0.0.1"), (Field::Port, "2222")], false)]
+    #[case::proxy_jump(&[(Field::HostName, "127.0.0.1"), (Field::ProxyJump, "bastion")], false)]
+    #[case::proxy_command(&[(Field::HostName, "127.0.0.1"), (Field::ProxyCommand, "nc %h %p")], false)]
+    #[case::local_forward(&[(Field::HostName, "127.0.0.1"), (Field::LocalForward, "8080 localhost:80")], false)]
+    fn detect_local_host(#[case] fields: &[(Field, &str)], #[case] expected: bool) {
+        // When:
+        let ssh_config = SshConfig {
+            host: "localhost".to_string(),
+            fields: fields.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+            annotations: Annotations::default(),
+        };
+        // Then:
+        assert_eq!(is_local(&ssh_config, Some("me")), expected);
+    }
+
+    #[test]
+    fn serialize_inventory_with_local_host_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "control".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "127.0.0.1".to_string()),
+                    (Field::User, "vagrant".to_string()),
+                    (Field::IdentitiesOnly, "yes".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "default".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "127.0.0.1".to_string()),
+                    (Field::Port, "2222".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let options = Options {
+            detect_local: true,
+            local_user: Some("vagrant".to_string()),
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    control:
+      ansible_connection: local
+    default:
+      ansible_host: 127.0.0.1
+      ansible_port: 2222
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_for_paramiko_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let mut ssh_configs = SshConfig::parse(&mut SAMPLE_PROXY_JUMP_SSH_CONFIG.as_bytes())?;
+        ssh_configs[1]
+            .fields
+            .insert(Field::StrictHostKeyChecking, "no".to_string());
+        ssh_configs[1]
+            .fields
+            .insert(Field::ControlPersist, "10m".to_string());
+        let options = Options {
+            proxy_jump: ProxyJumpMode::ProxyCommand,
+            connection: Connection::Paramiko,
+            mapping: Mapping::builtin(Connection::Paramiko),
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    app:
+      ansible_host: 10.0.0.1
+      ansible_paramiko_host_key_checking: false
+      ansible_paramiko_look_for_keys: false
+      ansible_paramiko_proxy_command: ssh -W %h:%p -i /path/to/bastion_key -l jump bastion.example.com
+    bastion:
+      ansible_host: bastion.example.com
+      ansible_user: jump
+      ansible_ssh_private_key_file: /path/to/bastion_key
+  vars:
+    ansible_connection: paramiko
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_sidecar_ssh_config_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = SshConfig::parse(&mut SAMPLE_PROXY_JUMP_SSH_CONFIG.as_bytes())?;
+        let options = Options {
+            sidecar: true,
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    app: {}
+    bastion: {}
+  vars:
+    ansible_ssh_common_args: -F {{ inventory_dir | quote }}/ssh_config
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_inventory_with_sidecar_ssh_config_and_sections() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host bastion
+  HostName 192.168.0.1
+# s2a-environment: staging
+Host web1
+  HostName 192.168.0.2
+# s2a-environment-end: staging
+"#
+        .as_bytes();
+        let ssh_configs = SshConfig::parse(&mut input)?;
+        let options = Options {
+            sidecar: true,
+            ..Options::default()
+        };
+
+        // When:
+        let result = Inventory::new("local", &ssh_configs, &None, &options);
+
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Invalid user input for arg \"--sidecar\". Reason: a single sidecar SSH config cannot hold the hosts of environments `local` and `staging`"
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::minimal(DefaultsMode::Minimal, "alice", "ansible_ssh_timeout: 30\n")]
+    #[case::explicit(
+        DefaultsMode::Explicit,
+        "bob",
+        r#"ansible_host: web1
+ansible_port: 22
+ansible_user: alice
+ansible_host_key_checking: true
+ansible_ssh_timeout: 30
+ansible_ssh_retries: 0
+"#
+    )]
+    fn new_host_params_with_defaults(
+        #[case] defaults: DefaultsMode,
+        #[case] local_user: &str,
+        #[case] expected: &str,
+    ) -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = SshConfig::parse(&mut SAMPLE_DEFAULTS_SSH_CONFIG.as_bytes())?;
+        let options = Options {
+            defaults,
+            local_user: Some(local_user.to_string()),
+            ..Options::default()
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_configs[0], &[], &options)?;
+
+        // Then:
+        assert_eq!(serde_yaml::to_string(&host_params)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_with_paths_relative_to_inventory() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([
+                (Field::IdentityFile, "keys/id_ed25519".to_string()),
+                (
+                    Field::CertificateFile,
+                    "keys/id_ed25519-cert.pub".to_string(),
+                ),
+                (Field::UserKnownHostsFile, "{{known}}".to_string()),
+                (Field::SetEnv, "X={{ y }}".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        };
+        let options = Options {
+            paths: PathsMode::RelativeToInventory,
+            directories: Directories::new(
+                PathBuf::from("/home/alice/project"),
+                Some(PathBuf::from("/home/alice")),
+                Some(Path::new("inventory/local.yaml")),
+            ),
+            ..Options::default()
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_config, &[], &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&host_params)?,
+            r#"ansible_ssh_private_key_file: '{{ inventory_dir }}/../keys/id_ed25519'
+ansible_ssh_extra_args: -o 'CertificateFile="'{{ inventory_dir | quote }}'/../keys/id_ed25519-cert.pub"' -o {% raw %}'SetEnv=X={{ y }}'{% endraw %} -o 'UserKnownHostsFile="'{{ inventory_dir | quote }}'/{% raw %}../{{known}}{% endraw %}"'
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_renamed_hosts_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "vagrant-web1".to_string(),
+                fields: IndexMap::from([(Field::Port, "2222".to_string())]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "vagrant-web2".to_string(),
+                fields: IndexMap::from([(Field::HostName, "10.0.0.2".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let options = Options {
+            naming: Naming {
+                template: Template::from_str("{alias}.{environment}").unwrap(),
+                rewrite: Some((Regex::new("^vagrant-").unwrap(), "".to_string())),
+            },
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("staging", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"staging:
+  hosts:
+    web1.staging:
+      ansible_host: vagrant-web1
+      ansible_port: 2222
+    web2.staging:
+      ansible_host: 10.0.0.2
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_inventory_with_host_name_collision() {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "web1".to_string(),
+                fields: IndexMap::from([(Field::HostName, "10.0.0.1".to_string())]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "web1-admin".to_string(),
+                fields: IndexMap::from([(Field::HostName, "10.0.0.1".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let options = Options {
+            naming: Naming {
+                template: Template::from_str("{HostName}").unwrap(),
+                rewrite: None,
+            },
+            ..Options::default()
+        };
+
+        // When:
+        let result = Inventory::new("local", &ssh_configs, &None, &options);
+
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Hosts \"web1\" and \"web1-admin\" are both named \"10.0.0.1\" in the inventory"
+        );
+    }
+
+    #[test]
+    fn serialize_inventory_with_merged_duplicates_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "db1".to_string(),
+                fields: IndexMap::from([(Field::HostName, "10.0.0.1".to_string())]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "db1-admin".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "10.0.0.1".to_string()),
+                    (Field::User, "admin".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let options = Options {
+            duplicates: DuplicatesPolicy::Merge,
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    db1:
+      ansible_host: 10.0.0.1
+      ssh_aliases:
+      - db1-admin
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_duplicates_merged_across_environments_to_yaml(
+    ) -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "db1".to_string(),
+                fields: IndexMap::from([(Field::HostName, "10.0.0.1".to_string())]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "db1-staging".to_string(),
+                fields: IndexMap::from([(Field::HostName, "10.0.0.1".to_string())]),
+                annotations: Annotations {
+                    groups: Vec::from(["backup".to_string()]),
+                    vars: IndexMap::from([("retention_days".to_string(), ValueType::Int64(7))]),
+                    description: None,
+                    environment: Some("staging".to_string()),
+                },
+            },
+        ]);
+        let options = Options {
+            duplicates: DuplicatesPolicy::Merge,
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    db1:
+      ansible_host: 10.0.0.1
+      retention_days: 7
+      ssh_aliases:
+      - db1-staging
+  children:
+    backup:
+      hosts:
+        db1: {}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_in_source_order_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "zeta".to_string(),
+                fields: IndexMap::from([
+                    (Field::LogLevel, "FATAL".to_string()),
+                    (Field::IdentitiesOnly, "yes".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "alpha".to_string(),
+                fields: IndexMap::from([(Field::Port, "2222".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let vars = Some(Vec::from([
+            ("zone".to_string(), ValueType::String("eu".to_string())),
+            ("become".to_string(), ValueType::Bool(true)),
+        ]));
+        let options = Options {
+            order: Order::Source,
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &vars, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    zeta:
+      ansible_ssh_extra_args: -o LogLevel=FATAL -o IdentitiesOnly=yes
+    alpha:
+      ansible_port: 2222
+  vars:
+    zone: eu
+    become: true
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_nested_environment_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([SshConfig {
+            host: "web1".to_string(),
+            fields: IndexMap::from([(Field::Port, "2222".to_string())]),
+            annotations: Annotations::default(),
+        }]);
+        let vars = Some(Vec::from([
+            ("http_port".to_string(), ValueType::Int64(8080)),
+            (
+                "prod/eu/region".to_string(),
+                ValueType::String("eu-west-1".to_string()),
+            ),
+            ("prod/tier".to_string(), ValueType::Int64(1)),
+        ]));
+
+        // When:
+        let inventory = Inventory::new("prod/eu/web", &ssh_configs, &vars, &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"all:
+  children:
+    prod:
+      children:
+        eu:
+          children:
+            web:
+              hosts:
+                web1:
+                  ansible_port: 2222
+              vars:
+                http_port: 8080
+          vars:
+            region: eu-west-1
+      vars:
+        tier: 1
+"#
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::empty_group(
+        "prod//web",
+        None,
+        "Invalid user input for arg \"--environment\". Reason: empty group in `prod//web`"
+    )]
+    #[case::unknown_scope(
+        "prod/web",
+        Some("staging/region"),
+        "Invalid user input for arg \"--var\". Reason: `staging` is not a group of environment `prod/web`"
+    )]
+    fn new_inventory_with_invalid_nested_environment(
+        #[case] environment: &str,
+        #[case] key: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        // Given:
+        let vars = key.map(|key| Vec::from([(key.to_string(), ValueType::Bool(true))]));
+        // When:
+        let result = Inventory::new(environment, &[], &vars, &Options::default());
+        // Then:
+        assert_eq!(result.err().unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn serialize_inventory_with_sections_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host bastion
+  HostName 192.168.0.1
+# s2a-environment: staging
+Host web1
+  HostName 192.168.0.2
+# s2a-environment-end: staging
+# Google Compute Engine Section
+Host web2
+  HostName 34.76.0.1
+# End of Google Compute Engine Section
+"#
+        .as_bytes();
+        let ssh_configs = SshConfig::parse(&mut input)?;
+        let vars = Some(Vec::from([(
+            "staging/tier".to_string(),
+            ValueType::Int64(2),
+        )]));
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &vars, &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"google_compute_engine:
+  hosts:
+    web2:
+      ansible_host: 34.76.0.1
+local:
+  hosts:
+    bastion:
+      ansible_host: 192.168.0.1
+staging:
+  hosts:
+    web1:
+      ansible_host: 192.168.0.2
+  vars:
+    tier: 2
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_nested_environment_and_sections_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host bastion
+  HostName 192.168.0.1
+# s2a-environment: staging
+Host web1
+  HostName 192.168.0.2
+# s2a-environment-end: staging
+# Google Compute Engine Section
+Host web2
+  HostName 34.76.0.1
+# End of Google Compute Engine Section
+"#
+        .as_bytes();
+        let ssh_configs = SshConfig::parse(&mut input)?;
+
+        // When:
+        let inventory = Inventory::new("prod/eu", &ssh_configs, &None, &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"all:
+  children:
+    google_compute_engine:
+      hosts:
+        web2:
+          ansible_host: 34.76.0.1
+    prod:
+      children:
+        eu:
+          hosts:
+            bastion:
+              ansible_host: 192.168.0.1
+    staging:
+      hosts:
+        web1:
+          ansible_host: 192.168.0.2
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_host_vars_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host db1
+  HostName 10.0.0.1
+Host web1
+  HostName 10.0.0.2
+Host web2
+  HostName 10.0.0.3
+"#
+        .as_bytes();
+        let ssh_configs = SshConfig::parse(&mut input)?;
+        let options = Options {
+            host_vars: Vec::from([
+                HostVar::from_str("db1:postgres_role:primary").unwrap(),
+                HostVar::from_str("web*:weight:10").unwrap(),
+                HostVar::from_str("cache*:weight:5").unwrap(),
+            ]),
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("prod", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"prod:
+  hosts:
+    db1:
+      ansible_host: 10.0.0.1
+      postgres_role: primary
+    web1:
+      ansible_host: 10.0.0.2
+      weight: 10
+    web2:
+      ansible_host: 10.0.0.3
+      weight: 10
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_groups_by_user_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "web1".to_string(),
+                fields: IndexMap::from([(Field::User, "deploy".to_string())]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "web2".to_string(),
+                fields: IndexMap::from([(Field::User, "deploy".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let options = Options {
+            group_by: Vec::from([GroupBy::from_str("User").unwrap()]),
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    web1:
+      ansible_user: deploy
+    web2:
+      ansible_user: deploy
+  children:
+    user_deploy:
+      hosts:
+        web1: {}
+        web2: {}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_tags_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([SshConfig {
+            host: "web1".to_string(),
+            fields: IndexMap::from([
+                (Field::Tag, "web local".to_string()),
+                (Field::LogLevel, "FATAL".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        }]);
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &Options::default())?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    web1:
+      ansible_ssh_extra_args: -o LogLevel=FATAL
+  children:
+    web:
+      hosts:
+        web1: {}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_annotations_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"# Primary database, do not reboot
+Host db1
+  # s2a: groups=db,primary
+  # s2a: var ansible_python_interpreter=/usr/bin/python3
+  # s2a: var postgres_port=5432
+  HostName 10.0.0.1
+"#
+        .as_bytes();
+        let ssh_configs = SshConfig::parse(&mut input)?;
+        let options = Options {
+            describe_hosts: true,
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("prod", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"prod:
+  hosts:
+    db1:
+      ansible_host: 10.0.0.1
+      ansible_python_interpreter: /usr/bin/python3
+      description: Primary database, do not reboot
+      postgres_port: 5432
+  children:
+    db:
+      hosts:
+        db1: {}
+    primary:
+      hosts:
+        db1: {}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_host_params_with_custom_mapping() -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "default".to_string(),
+            fields: IndexMap::from([
+                (Field::HostName, "127.0.0.1".to_string()),
+                (Field::IdentityAgent, "/run/agent.sock".to_string()),
+                (Field::LogLevel, "FATAL".to_string()),
+                (Field::ForwardAgent, "yes".to_string()),
+                (Field::ProxyCommand, "ssh -W %h:%p bastion".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        };
+        let mapping = Mapping::load(
+            Connection::Ssh,
+            r#"IdentityAgent:
+  placement: host-var
+  variable: ssh_agent_socket
+LogLevel:
+  placement: drop
+ForwardAgent:
+  placement: common-args
+"#
+            .as_bytes(),
+        )?;
+        let options = Options {
+            mapping,
+            ..Options::default()
+        };
+
+        // When:
+        let host_params = HostParams::new(&ssh_config, &[], &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&host_params)?,
+            r#"ansible_host: 127.0.0.1
+ansible_ssh_common_args: -o ForwardAgent=yes -o 'ProxyCommand=ssh -W %h:%p bastion'
+ssh_agent_socket: /run/agent.sock
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_options_as_vars_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "host1".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "192.168.0.1".to_string()),
+                    (Field::IdentitiesOnly, "yes".to_string()),
+                    (Field::LogLevel, "FATAL".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "host2".to_string(),
+                fields: IndexMap::from([(Field::HostName, "192.168.0.2".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let vars = Some(Vec::from([("become".to_string(), ValueType::Bool(true))]));
+        let options = Options {
+            options_as_vars: true,
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &vars, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    host1:
+      ansible_host: 192.168.0.1
+      ssh_options:
+        IdentitiesOnly: yes
+        LogLevel: FATAL
+    host2:
+      ansible_host: 192.168.0.2
+  vars:
+    ansible_ssh_extra_args: '{% for option, value in (ssh_options | default({}) | combine(ssh_options_overrides | default({}))).items() %}-o {{ (option ~ ''='' ~ ((value | ternary(''yes'', ''no'')) if value is boolean else value)) | quote }}{% if not loop.last %} {% endif %}{% endfor %}'
+    become: true
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_hoisted_vars_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = SshConfig::parse(&mut SAMPLE_VAGRANT_SSH_CONFIG.as_bytes())?;
+        let vars = Some(Vec::from([(
+            "ansible_user".to_string(),
+            ValueType::String("root".to_string()),
+        )]));
+        let options = Options {
+            hoist_threshold: Some(1.0),
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &vars, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    node1:
+      ansible_port: 2222
+      ansible_user: vagrant
+      ansible_ssh_private_key_file: /path/to/private_key
+    node2:
+      ansible_port: 2223
+      ansible_user: vagrant
+      ansible_ssh_private_key_file: /path/to/private_key
+    node3:
+      ansible_port: 2224
+      ansible_user: vagrant
+      ansible_ssh_private_key_file: /path/to/other_key
+  vars:
+    ansible_host: 127.0.0.1
+    ansible_ssh_extra_args: -o IdentitiesOnly=yes -o LogLevel=FATAL
+    ansible_user: root
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_inventory_with_vars_hoisted_from_most_hosts_to_yaml() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = SshConfig::parse(&mut SAMPLE_VAGRANT_SSH_CONFIG.as_bytes())?;
+        let options = Options {
+            hoist_threshold: Some(0.6),
+            ..Options::default()
+        };
+
+        // When:
+        let inventory = Inventory::new("local", &ssh_configs, &None, &options)?;
+
+        // Then:
+        assert_eq!(
+            serde_yaml::to_string(&inventory)?,
+            r#"local:
+  hosts:
+    node1:
+      ansible_port: 2222
+    node2:
+      ansible_port: 2223
+    node3:
+      ansible_port: 2224
+      ansible_ssh_private_key_file: /path/to/other_key
+  vars:
+    ansible_host: 127.0.0.1
+    ansible_ssh_extra_args: -o IdentitiesOnly=yes -o LogLevel=FATAL
+    ansible_ssh_private_key_file: /path/to/private_key
+    ansible_user: vagrant
+"#
+        );
+        Ok(())
+    }
+
+    /// Resolve the variables of each host, as Ansible does: host variables over group variables.
+    fn resolve(inventory: &Inventory) -> BTreeMap<String, BTreeMap<String, ValueType>> {
+        inventory
+            .groups
+            .values()
+            .flat_map(|group| {
+                group.hosts.iter().flatten().map(|(host, host_params)| {
+                    let mut vars = BTreeMap::from_iter(group.vars.clone().unwrap_or_default());
+                    vars.extend(host_params.vars.clone());
+                    (host.to_owned(), vars)
+                })
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn hoisting_preserves_the_variables_of_each_host(
+            hosts in prop::collection::vec(
+                prop::collection::btree_map(
+                    prop::sample::select(vec![Field::HostName, Field::User, Field::Port, Field::LogLevel]),
+                    prop::sample::select(vec!["1", "2", "3"]),
+                    0..4,
+                ),
+                0..6,
+            ),
+            threshold in 0.01..=1.0,
+        ) {
+            // Given:
+            let ssh_configs = hosts
+                .into_iter()
+                .enumerate()
+                .map(|(i, fields)| SshConfig {
+                    host: format!("host{i}"),
+                    fields: fields.into_iter().map(|(k, v)| (k, v.to_string())).collect(),
+                    annotations: Annotations::default(),
+                })
+                .collect::<Vec<SshConfig>>();
+            let vars = Some(Vec::from([("ansible_user".to_string(), ValueType::Int64(1))]));
+            let options = Options {
+                hoist_threshold: Some(threshold),
+                ..Options::default()
+            };
+
+            // When:
+            let inventory = Inventory::new("local", &ssh_configs, &vars, &Options::default()).unwrap();
+            let hoisted = Inventory::new("local", &ssh_configs, &vars, &options).unwrap();
+
+            // Then:
+            prop_assert_eq!(resolve(&hoisted), resolve(&inventory));
+        }
+    }
 }
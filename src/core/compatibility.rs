@@ -0,0 +1,113 @@
+use crate::core::mapping::{Placement, Rule, Transform};
+use crate::core::ssh_config::Field;
+
+/// Incompatibility tells why forwarding a SSH option as a `-o` argument breaks Ansible, and how
+/// to place this option instead.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Incompatibility {
+    pub rule: Rule,
+    pub reason: &'static str,
+}
+
+impl Incompatibility {
+    fn dropped(reason: &'static str) -> Option<Incompatibility> {
+        Some(Incompatibility {
+            rule: Rule::placed(Placement::Drop),
+            reason,
+        })
+    }
+}
+
+/// Check whether forwarding the provided SSH option as a `-o` argument breaks Ansible.
+pub fn check(field: Field, value: &str) -> Option<Incompatibility> {
+    let value = value.to_lowercase();
+    match field {
+        Field::RemoteCommand => {
+            Incompatibility::dropped("Ansible runs its own commands on the remote host")
+        }
+        Field::RequestTTY if value == "force" => Incompatibility::dropped(
+            "a forced TTY mangles the output of Ansible's modules, Ansible requests one itself when needed",
+        ),
+        Field::LocalCommand => Incompatibility::dropped(
+            "the local command would run on each of Ansible's many connections",
+        ),
+        Field::PermitLocalCommand if value == "yes" => Incompatibility::dropped(
+            "the local command would run on each of Ansible's many connections",
+        ),
+        Field::ForkAfterAuthentication if value == "yes" => Incompatibility::dropped(
+            "ssh would go to the background before Ansible's commands run",
+        ),
+        Field::SessionType if value == "none" => {
+            Incompatibility::dropped("Ansible's commands would not run")
+        }
+        Field::StdinNull if value == "yes" => {
+            Incompatibility::dropped("Ansible sends its modules via stdin")
+        }
+        Field::ControlMaster => Some(Incompatibility {
+            rule: Rule::placed(Placement::SshArgs),
+            reason: "Ansible sets its own ControlMaster, which this option overrides instead",
+        }),
+        Field::ControlPath => Some(Incompatibility {
+            rule: Rule::host_var("ansible_control_path", Some(Transform::EscapePercent)),
+            reason: "Ansible sets its own ControlPath, configured via ansible_control_path instead",
+        }),
+        Field::LocalForward | Field::RemoteForward => Incompatibility::dropped(
+            "the forwarding fails on all but the first of Ansible's connections, as the port is then already bound",
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::core::mapping::{Placement, Rule, Transform};
+    use crate::core::ssh_config::Field;
+    use rstest::rstest;
+
+    #[rstest]
+    // Given:
+    #[case::remote_command(
+        Field::RemoteCommand,
+        "tmux attach",
+        Some(Rule::placed(Placement::Drop))
+    )]
+    #[case::request_tty_force(Field::RequestTTY, "force", Some(Rule::placed(Placement::Drop)))]
+    #[case::request_tty_auto(Field::RequestTTY, "auto", None)]
+    #[case::local_command(Field::LocalCommand, "no", Some(Rule::placed(Placement::Drop)))]
+    #[case::permit_local_command_yes(
+        Field::PermitLocalCommand,
+        "yes",
+        Some(Rule::placed(Placement::Drop))
+    )]
+    #[case::permit_local_command_no(Field::PermitLocalCommand, "no", None)]
+    #[case::fork_after_authentication(
+        Field::ForkAfterAuthentication,
+        "yes",
+        Some(Rule::placed(Placement::Drop))
+    )]
+    #[case::session_type_none(Field::SessionType, "none", Some(Rule::placed(Placement::Drop)))]
+    #[case::session_type_default(Field::SessionType, "default", None)]
+    #[case::stdin_null(Field::StdinNull, "Yes", Some(Rule::placed(Placement::Drop)))]
+    #[case::control_master(Field::ControlMaster, "no", Some(Rule::placed(Placement::SshArgs)))]
+    #[case::control_path(
+        Field::ControlPath,
+        "~/.ssh/cm-%C",
+        Some(Rule::host_var("ansible_control_path", Some(Transform::EscapePercent)))
+    )]
+    #[case::local_forward(
+        Field::LocalForward,
+        "8080 localhost:80",
+        Some(Rule::placed(Placement::Drop))
+    )]
+    #[case::compatible(Field::LogLevel, "FATAL", None)]
+    fn check_option(#[case] field: Field, #[case] value: &str, #[case] expected: Option<Rule>) {
+        // When:
+        let incompatibility = check(field, value);
+        // Then:
+        assert_eq!(
+            incompatibility.map(|incompatibility| incompatibility.rule),
+            expected
+        );
+    }
+}
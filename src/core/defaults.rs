@@ -0,0 +1,164 @@
+use crate::core::mapping::Connection;
+use crate::core::ssh_config::Field;
+use crate::core::variables::ValueType;
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+/// DefaultsMode controls how values equal to OpenSSH's or Ansible's defaults are emitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DefaultsMode {
+    // Emit values as per the SSH configuration.
+    #[default]
+    AsIs,
+    // Omit values equal to OpenSSH's or Ansible's defaults.
+    Minimal,
+    // Fill in every connection variable with its effective default.
+    Explicit,
+}
+
+/// Ansible's default ssh arguments, which `ansible_ssh_args` replaces.
+pub const ANSIBLE_SSH_ARGS: [(Field, &str); 2] = [
+    (Field::ControlMaster, "auto"),
+    (Field::ControlPersist, "60s"),
+];
+
+/// Ansible's default connection timeout, in seconds.
+const ANSIBLE_TIMEOUT: i64 = 10;
+
+/// OpenSSH's defaults for commonly set options. See also:
+/// http://man.openbsd.org/OpenBSD-current/man5/ssh_config.5
+const OPENSSH_DEFAULTS: [(Field, &str); 15] = [
+    (Field::AddressFamily, "any"),
+    (Field::BatchMode, "no"),
+    (Field::CheckHostIP, "no"),
+    (Field::Compression, "no"),
+    (Field::ConnectionAttempts, "1"),
+    (Field::ForwardAgent, "no"),
+    (Field::ForwardX11, "no"),
+    (Field::IdentitiesOnly, "no"),
+    (Field::LogLevel, "INFO"),
+    (Field::PasswordAuthentication, "yes"),
+    (Field::Port, "22"),
+    (Field::PubkeyAuthentication, "yes"),
+    (Field::ServerAliveInterval, "0"),
+    (Field::TCPKeepAlive, "yes"),
+    (Field::VisualHostKey, "no"),
+];
+
+/// Remove the options equal to OpenSSH's defaults, given the host's alias, which is also its
+/// default `HostName`, and the local user, which is also the default `User`.
+pub fn minimise(alias: &str, fields: &mut IndexMap<Field, String>, local_user: Option<&str>) {
+    fields.retain(|field, value| {
+        let is_default = match field {
+            Field::HostName => value.eq_ignore_ascii_case(alias),
+            Field::User => local_user == Some(value.as_str()),
+            _ => OPENSSH_DEFAULTS
+                .iter()
+                .any(|(other, default)| other == field && value.eq_ignore_ascii_case(default)),
+        };
+        if is_default {
+            debug!(host = alias, %field, value, "Omitted SSH option set to its default");
+        }
+        !is_default
+    });
+}
+
+/// Fill in the connection variables not set yet with their effective default, so that the
+/// resulting inventory does not depend on whoever runs Ansible, and how Ansible is configured.
+/// `ansible_ssh_args` is left out, as it would replace ansible.cfg's `ssh_args` as a whole, rather
+/// than a single setting.
+pub fn fill(
+    alias: &str,
+    vars: &mut BTreeMap<String, ValueType>,
+    connection: Connection,
+    local_user: Option<&str>,
+) {
+    let mut defaults = vec![
+        ("ansible_host", ValueType::String(alias.to_owned())),
+        ("ansible_port", ValueType::Int64(22)),
+    ];
+    if let Some(local_user) = local_user {
+        defaults.push(("ansible_user", ValueType::String(local_user.to_owned())));
+    }
+    match connection {
+        Connection::Ssh => defaults.extend([
+            ("ansible_host_key_checking", ValueType::Bool(true)),
+            ("ansible_ssh_timeout", ValueType::Int64(ANSIBLE_TIMEOUT)),
+            ("ansible_ssh_retries", ValueType::Int64(0)),
+        ]),
+        Connection::Paramiko => defaults.extend([
+            ("ansible_paramiko_host_key_checking", ValueType::Bool(true)),
+            ("ansible_paramiko_look_for_keys", ValueType::Bool(true)),
+            (
+                "ansible_paramiko_timeout",
+                ValueType::Int64(ANSIBLE_TIMEOUT),
+            ),
+        ]),
+    }
+    for (name, value) in defaults {
+        vars.entry(name.to_string()).or_insert(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill, minimise};
+    use crate::core::mapping::Connection;
+    use crate::core::ssh_config::Field;
+    use crate::core::variables::ValueType;
+    use indexmap::IndexMap;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn minimise_fields() {
+        // Given:
+        let mut fields = IndexMap::from([
+            (Field::HostName, "Web1".to_string()),
+            (Field::Port, "22".to_string()),
+            (Field::User, "alice".to_string()),
+            (Field::LogLevel, "info".to_string()),
+            (Field::IdentitiesOnly, "yes".to_string()),
+        ]);
+
+        // When:
+        minimise("web1", &mut fields, Some("alice"));
+
+        // Then:
+        assert_eq!(
+            fields,
+            IndexMap::from([(Field::IdentitiesOnly, "yes".to_string())])
+        );
+    }
+
+    #[test]
+    fn fill_vars() {
+        // Given:
+        let mut vars = BTreeMap::from([("ansible_port".to_string(), ValueType::Int64(2222))]);
+
+        // When:
+        fill("web1", &mut vars, Connection::Paramiko, None);
+
+        // Then:
+        assert_eq!(
+            vars,
+            BTreeMap::from([
+                (
+                    "ansible_host".to_string(),
+                    ValueType::String("web1".to_string())
+                ),
+                (
+                    "ansible_paramiko_host_key_checking".to_string(),
+                    ValueType::Bool(true)
+                ),
+                (
+                    "ansible_paramiko_look_for_keys".to_string(),
+                    ValueType::Bool(true)
+                ),
+                ("ansible_paramiko_timeout".to_string(), ValueType::Int64(10)),
+                ("ansible_port".to_string(), ValueType::Int64(2222)),
+            ])
+        );
+    }
+}
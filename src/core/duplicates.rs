@@ -0,0 +1,162 @@
+use crate::core::ssh_config::{Field, SshConfig};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// DuplicatesPolicy controls what to do with different hosts connecting to the same endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DuplicatesPolicy {
+    // Keep every host, with a warning.
+    #[default]
+    KeepAll,
+    // Keep the first host only.
+    KeepFirst,
+    // Keep the first host only, recording the other aliases in its ssh_aliases variable.
+    Merge,
+}
+
+/// Where ssh actually connects to, given a host's SSH configuration. Hosts reached via different
+/// jump hosts or proxy commands may well be different machines, despite sharing a `HostName`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Endpoint {
+    host_name: String,
+    port: String,
+    user: Option<String>,
+    proxy_jump: Option<String>,
+    proxy_command: Option<String>,
+}
+
+impl Endpoint {
+    /// The endpoint of the provided host, if this is a single host rather than a pattern.
+    fn new(ssh_config: &SshConfig, by_user: bool) -> Option<Endpoint> {
+        if ssh_config
+            .host
+            .contains(|c: char| c.is_whitespace() || "*?!".contains(c))
+        {
+            return None;
+        }
+        let field = |field| ssh_config.fields.get(&field).cloned();
+        Some(Endpoint {
+            host_name: ssh_config.host_name().to_lowercase(),
+            port: field(Field::Port).unwrap_or_else(|| "22".to_string()),
+            user: field(Field::User).filter(|_| by_user),
+            proxy_jump: field(Field::ProxyJump),
+            proxy_command: field(Field::ProxyCommand),
+        })
+    }
+}
+
+/// Report the hosts connecting to the same endpoint as a previous host, i.e. to the same
+/// `HostName` and `Port`, and `User` if requested, and apply the provided policy to them. Return
+/// the hosts to keep, in order, and the aliases merged into each kept host, which also gets the
+/// annotations of the hosts merged into it.
+pub fn deduplicate(
+    ssh_configs: &[SshConfig],
+    policy: DuplicatesPolicy,
+    by_user: bool,
+) -> (Vec<SshConfig>, BTreeMap<String, Vec<String>>) {
+    // Index of the first host connecting to each endpoint, among the hosts to keep:
+    let mut firsts = BTreeMap::<Endpoint, usize>::new();
+    let mut kept: Vec<SshConfig> = Vec::with_capacity(ssh_configs.len());
+    let mut merged = BTreeMap::<String, Vec<String>>::new();
+    for ssh_config in ssh_configs {
+        let Some(endpoint) = Endpoint::new(ssh_config, by_user) else {
+            kept.push(ssh_config.clone());
+            continue;
+        };
+        let first = *firsts.entry(endpoint).or_insert(kept.len());
+        // Repeated Host blocks for the same alias are the same host:
+        if first == kept.len() || kept[first].host == ssh_config.host {
+            kept.push(ssh_config.clone());
+            continue;
+        }
+        warn!(
+            host = ssh_config.host,
+            first = kept[first].host,
+            "Host connects to the same endpoint as a previous host"
+        );
+        match policy {
+            DuplicatesPolicy::KeepAll => kept.push(ssh_config.clone()),
+            DuplicatesPolicy::KeepFirst => {}
+            DuplicatesPolicy::Merge => {
+                let aliases = merged.entry(kept[first].host.to_owned()).or_default();
+                if !aliases.contains(&ssh_config.host) {
+                    aliases.push(ssh_config.host.to_owned());
+                }
+                kept[first].annotations.merge(&ssh_config.annotations);
+            }
+        }
+    }
+    (kept, merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deduplicate, DuplicatesPolicy};
+    use crate::core::ssh_config::{Annotations, Field, SshConfig};
+    use rstest::rstest;
+    use std::collections::BTreeMap;
+
+    fn sample_ssh_configs() -> Vec<SshConfig> {
+        let ssh_config = |host: &str, fields: &[(Field, &str)]| SshConfig {
+            host: host.to_string(),
+            fields: fields
+                .iter()
+                .map(|(field, value)| (*field, value.to_string()))
+                .collect(),
+            annotations: Annotations::default(),
+        };
+        Vec::from([
+            ssh_config("db1", &[(Field::HostName, "10.0.0.1")]),
+            ssh_config(
+                "db1-admin",
+                &[
+                    (Field::HostName, "10.0.0.1"),
+                    (Field::Port, "22"),
+                    (Field::User, "admin"),
+                ],
+            ),
+            ssh_config(
+                "db1-other-port",
+                &[(Field::HostName, "10.0.0.1"), (Field::Port, "2222")],
+            ),
+            ssh_config(
+                "db1-via-bastion",
+                &[(Field::HostName, "10.0.0.1"), (Field::ProxyJump, "bastion")],
+            ),
+            ssh_config("10.0.0.1", &[]),
+            ssh_config("db*", &[(Field::HostName, "10.0.0.1")]),
+        ])
+    }
+
+    #[rstest]
+    // Given:
+    #[case::keep_all(DuplicatesPolicy::KeepAll, false, &["db1", "db1-admin", "db1-other-port", "db1-via-bastion", "10.0.0.1", "db*"], &[])]
+    #[case::keep_first(DuplicatesPolicy::KeepFirst, false, &["db1", "db1-other-port", "db1-via-bastion", "db*"], &[])]
+    #[case::merge(DuplicatesPolicy::Merge, false, &["db1", "db1-other-port", "db1-via-bastion", "db*"], &[("db1", "db1-admin"), ("db1", "10.0.0.1")])]
+    #[case::merge_by_user(DuplicatesPolicy::Merge, true, &["db1", "db1-admin", "db1-other-port", "db1-via-bastion", "db*"], &[("db1", "10.0.0.1")])]
+    fn deduplicate_hosts(
+        #[case] policy: DuplicatesPolicy,
+        #[case] by_user: bool,
+        #[case] expected_hosts: &[&str],
+        #[case] expected_merged: &[(&str, &str)],
+    ) {
+        // When:
+        let (kept, merged) = deduplicate(&sample_ssh_configs(), policy, by_user);
+        // Then:
+        assert_eq!(
+            kept.iter()
+                .map(|ssh_config| ssh_config.host.as_str())
+                .collect::<Vec<&str>>(),
+            expected_hosts
+        );
+        let mut expected = BTreeMap::<String, Vec<String>>::new();
+        for (first, other) in expected_merged {
+            expected
+                .entry(first.to_string())
+                .or_default()
+                .push(other.to_string());
+        }
+        assert_eq!(merged, expected);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::common::error::AppError;
+use crate::core::naming::{self, GroupNamePolicy};
+use crate::core::ssh_config::{Field, SshConfig};
+use indexmap::IndexMap;
+use regex::Regex;
+use std::str::FromStr;
+
+lazy_static! {
+    /// Python-like `split('-')[0]`, with either quotes.
+    static ref SPLIT: Regex =
+        Regex::new(r#"^split\((?:'(?<single>[^']*)'|"(?<double>[^"]*)")\)\[(?<index>-?\d+)\]$"#)
+            .unwrap();
+}
+
+/// What hosts are grouped by, or rules check the value of: the alias, or any SSH option.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Key {
+    Alias,
+    Field(Field),
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Key, String> {
+        if key == "alias" {
+            return Ok(Key::Alias);
+        }
+        Ok(Key::Field(
+            Field::from_key(key).ok_or_else(|| format!("unknown key `{key}`"))?,
+        ))
+    }
+}
+
+impl Key {
+    /// The value of this key for the provided host, if any.
+    pub fn value<'a>(&self, ssh_config: &'a SshConfig) -> Option<&'a str> {
+        match self {
+            Key::Alias => Some(&ssh_config.host),
+            Key::Field(Field::HostName) => Some(ssh_config.host_name()),
+            Key::Field(field) => ssh_config.fields.get(field).map(String::as_str),
+        }
+    }
+}
+
+/// How the value hosts are grouped by is transformed, before naming their group.
+#[derive(Clone, Debug, PartialEq)]
+enum Transform {
+    /// Everything after the first dot, e.g. `example.com` in `web1.example.com`.
+    Domain,
+    /// The part at the provided index, negative from the end, once split on the provided
+    /// separator.
+    Split { separator: String, index: i64 },
+}
+
+impl Transform {
+    fn apply<'a>(&self, value: &'a str) -> Option<&'a str> {
+        match self {
+            Transform::Domain => value.split_once('.').map(|(_, domain)| domain),
+            Transform::Split { separator, index } => {
+                let parts = value.split(separator.as_str()).collect::<Vec<&str>>();
+                let index = if *index < 0 {
+                    parts.len().checked_sub(index.unsigned_abs() as usize)?
+                } else {
+                    *index as usize
+                };
+                parts.get(index).copied()
+            }
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    fn from_str(transform: &str) -> Result<Transform, String> {
+        if transform == "domain" {
+            return Ok(Transform::Domain);
+        }
+        let captures = SPLIT.captures(transform).ok_or_else(|| {
+            format!("unknown transform `{transform}`, expected domain or split('SEPARATOR')[INDEX]")
+        })?;
+        let separator = captures
+            .name("single")
+            .or_else(|| captures.name("double"))
+            .map_or("", |separator| separator.as_str());
+        if separator.is_empty() {
+            return Err(format!("empty separator in `{transform}`"));
+        }
+        Ok(Transform::Split {
+            separator: separator.to_owned(),
+            index: captures["index"]
+                .parse()
+                .map_err(|e| format!("invalid index in `{transform}`: {e}"))?,
+        })
+    }
+}
+
+/// GroupBy groups hosts by the value of their alias or of any SSH option, optionally transformed,
+/// in groups named `{prefix}_{value}`, e.g. `user_vagrant` for `User`, parsed from
+/// `[PREFIX=]KEY[:TRANSFORM]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupBy {
+    prefix: String,
+    key: Key,
+    transform: Option<Transform>,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(group_by: &str) -> Result<GroupBy, String> {
+        let (prefix, rest) = match group_by.split_once('=') {
+            // Not an equal sign within the transform, e.g. `split('=')[0]`:
+            Some((prefix, rest)) if !prefix.contains(':') => (Some(prefix), rest),
+            _ => (None, group_by),
+        };
+        let (key, transform) = match rest.split_once(':') {
+            Some((key, transform)) => (key, Some(Transform::from_str(transform)?)),
+            None => (rest, None),
+        };
+        let key = Key::from_str(key)?;
+        let prefix = prefix.map_or_else(
+            || match &key {
+                Key::Alias => "alias".to_string(),
+                Key::Field(field) => field.to_string().to_lowercase(),
+            },
+            str::to_owned,
+        );
+        Ok(GroupBy {
+            prefix,
+            key,
+            transform,
+        })
+    }
+}
+
+impl GroupBy {
+    /// The name of the group of the provided host, if it has a value to be grouped by.
+    fn group(&self, ssh_config: &SshConfig) -> Option<String> {
+        let value = self.key.value(ssh_config)?;
+        let value = match &self.transform {
+            Some(transform) => transform.apply(value)?,
+            None => value,
+        };
+        if value.is_empty() {
+            return None;
+        }
+        Some(naming::sanitise(&if self.prefix.is_empty() {
+            value.to_owned()
+        } else {
+            format!("{}_{value}", self.prefix)
+        }))
+    }
+}
+
+/// Group the provided hosts, named as provided in the inventory, by each of their tags, i.e. their
+/// whitespace or comma-separated `Tag` values, by each of their annotated groups, and by each of
+/// the provided keys. Return the names of the hosts of each group, in the order of the hosts.
+pub fn group(
+    ssh_configs: &[SshConfig],
+    names: &[String],
+    group_by: &[GroupBy],
+    policy: GroupNamePolicy,
+) -> Result<IndexMap<String, Vec<String>>, AppError> {
+    let mut groups = IndexMap::<String, Vec<String>>::new();
+    for (ssh_config, name) in ssh_configs.iter().zip(names) {
+        let named = ssh_config
+            .fields
+            .get(&Field::Tag)
+            .into_iter()
+            .flat_map(|tags| tags.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|tag| !tag.is_empty())
+            .chain(ssh_config.annotations.groups.iter().map(String::as_str))
+            .map(|group| naming::group_name(group, policy))
+            .collect::<Result<Vec<String>, AppError>>()?;
+        let keyed = group_by
+            .iter()
+            .filter_map(|group_by| group_by.group(ssh_config));
+        for group in named.into_iter().chain(keyed) {
+            let hosts = groups.entry(group).or_default();
+            if !hosts.contains(name) {
+                hosts.push(name.to_owned());
+            }
+        }
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group, GroupBy};
+    use crate::common::error::AppError;
+    use crate::core::naming::GroupNamePolicy;
+    use crate::core::ssh_config::{Annotations, Field, SshConfig};
+    use indexmap::IndexMap;
+    use rstest::rstest;
+    use std::str::FromStr;
+
+    fn sample_ssh_configs() -> Vec<SshConfig> {
+        Vec::from([
+            SshConfig {
+                host: "web-eu-1".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "web1.eu.example.com".to_string()),
+                    (Field::User, "deploy".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "db-us-1".to_string(),
+                fields: IndexMap::from([(Field::User, "vagrant".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ])
+    }
+
+    #[rstest]
+    // Given:
+    #[case::field("User", &[("user_deploy", "web-eu-1"), ("user_vagrant", "db-us-1")])]
+    #[case::domain("HostName:domain", &[("hostname_eu_example_com", "web-eu-1")])]
+    #[case::split("alias:split('-')[0]", &[("alias_web", "web-eu-1"), ("alias_db", "db-us-1")])]
+    #[case::split_from_end("region=alias:split(\"-\")[-2]", &[("region_eu", "web-eu-1"), ("region_us", "db-us-1")])]
+    #[case::without_prefix("=alias:split('-')[0]", &[("web", "web-eu-1"), ("db", "db-us-1")])]
+    #[case::out_of_range("alias:split('-')[5]", &[])]
+    fn group_hosts(#[case] group_by: &str, #[case] expected: &[(&str, &str)]) {
+        // Given:
+        let ssh_configs = sample_ssh_configs();
+        let names = Vec::from(["web-eu-1".to_string(), "db-us-1".to_string()]);
+        // When:
+        let groups = group(
+            &ssh_configs,
+            &names,
+            &[GroupBy::from_str(group_by).unwrap()],
+            GroupNamePolicy::Allow,
+        )
+        .unwrap();
+        // Then:
+        assert_eq!(
+            groups,
+            expected
+                .iter()
+                .map(|(group, host)| (group.to_string(), Vec::from([host.to_string()])))
+                .collect::<IndexMap<String, Vec<String>>>()
+        );
+    }
+
+    #[rstest]
+    // Given:
+    #[case::unknown_key("Foo", "unknown key `Foo`")]
+    #[case::unknown_transform(
+        "User:upper",
+        "unknown transform `upper`, expected domain or split('SEPARATOR')[INDEX]"
+    )]
+    #[case::empty_separator("alias:split('')[0]", "empty separator in `split('')[0]`")]
+    fn parse_invalid_group_by(#[case] group_by: &str, #[case] expected: &str) {
+        // When:
+        let result = GroupBy::from_str(group_by);
+        // Then:
+        assert_eq!(result.err().unwrap(), expected);
+    }
+
+    #[test]
+    fn group_hosts_by_tags() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            SshConfig {
+                host: "web1".to_string(),
+                fields: IndexMap::from([(Field::Tag, "web prod".to_string())]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "web2".to_string(),
+                fields: IndexMap::from([(Field::Tag, "web,canary".to_string())]),
+                annotations: Annotations::default(),
+            },
+        ]);
+        let names = Vec::from(["web1".to_string(), "web2".to_string()]);
+
+        // When:
+        let groups = group(&ssh_configs, &names, &[], GroupNamePolicy::Allow)?;
+
+        // Then:
+        assert_eq!(
+            groups,
+            IndexMap::from([
+                (
+                    "web".to_string(),
+                    Vec::from(["web1".to_string(), "web2".to_string()])
+                ),
+                ("prod".to_string(), Vec::from(["web1".to_string()])),
+                ("canary".to_string(), Vec::from(["web2".to_string()])),
+            ])
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,449 @@
+use crate::common::error::AppError;
+use crate::core::ssh_config::{Field, SshConfig};
+use crate::core::variables::ValueType;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Connection is the Ansible connection plugin to target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Connection {
+    // OpenSSH, Ansible's default.
+    #[default]
+    Ssh,
+    // Paramiko, which ignores ssh arguments and has its own ansible_paramiko_* variables.
+    Paramiko,
+}
+
+/// Placement tells where a SSH option lands in the Ansible inventory.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// As the host variable named after the rule's `variable`.
+    HostVar,
+    /// As a `-o` option in `ansible_ssh_args`, alongside Ansible's default ssh arguments.
+    SshArgs,
+    /// As a `-o` option in `ansible_ssh_common_args`.
+    CommonArgs,
+    /// As a `-o` option in `ansible_ssh_extra_args`.
+    ExtraArgs,
+    /// Nowhere, the option is dropped.
+    Drop,
+}
+
+/// Transform converts the value of a SSH option placed as a host variable.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transform {
+    /// Keep the value as a string.
+    String,
+    /// Convert the value into an integer.
+    Integer,
+    /// Convert the value into a port number, i.e. an integer between 1 and 65535.
+    Port,
+    /// Convert the value into an integer, minus one but at least zero, e.g. to turn a number of
+    /// attempts into a number of retries.
+    Decrement,
+    /// Convert `yes`, `true`, `on` and `ask` into `true`, and `no`, `false` and `off` into `false`.
+    /// Any other value falls back to `ansible_ssh_extra_args`.
+    Boolean,
+    /// Same as `boolean`, negated, e.g. to turn `IdentitiesOnly` into paramiko's `look_for_keys`.
+    NegatedBoolean,
+    /// Keep the value as a string, with `%` escaped as `%%`, e.g. for `ansible_control_path`,
+    /// which Ansible formats with `%` before passing it to ssh, e.g. to expand `%(directory)s`.
+    EscapePercent,
+}
+
+/// Rule maps a SSH option to its place in the Ansible inventory.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub placement: Placement,
+    #[serde(default)]
+    pub variable: Option<String>,
+    #[serde(default)]
+    pub transform: Option<Transform>,
+}
+
+impl Rule {
+    pub fn host_var(variable: &str, transform: Option<Transform>) -> Rule {
+        Rule {
+            placement: Placement::HostVar,
+            variable: Some(variable.to_string()),
+            transform,
+        }
+    }
+
+    pub fn placed(placement: Placement) -> Rule {
+        Rule {
+            placement,
+            variable: None,
+            transform: None,
+        }
+    }
+
+    /// Convert the provided value as per this rule's transform. `None` means the transform does
+    /// not apply to this value, which should then fall back to `ansible_ssh_extra_args`.
+    pub fn transform(
+        &self,
+        ssh_config: &SshConfig,
+        field: Field,
+        value: String,
+        text: impl Fn(String) -> ValueType,
+    ) -> Result<Option<ValueType>, AppError> {
+        let integer = |value: &str| {
+            value
+                .parse::<i64>()
+                .ok()
+                .filter(|integer| *integer >= 0)
+                .ok_or_else(|| AppError::InvalidSshConfig {
+                    host: ssh_config.host.to_owned(),
+                    reason: format!("{field} is not a non-negative integer: {value}"),
+                })
+        };
+        let value = match self.transform.unwrap_or(Transform::String) {
+            Transform::String => text(value),
+            Transform::EscapePercent => text(value.replace('%', "%%")),
+            Transform::Integer => ValueType::Int64(integer(&value)?),
+            Transform::Port => ValueType::Int64(
+                value
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|port| *port > 0)
+                    .ok_or_else(|| AppError::InvalidSshConfig {
+                        host: ssh_config.host.to_owned(),
+                        reason: format!("{field} is not a port between 1 and 65535: {value}"),
+                    })?
+                    .into(),
+            ),
+            Transform::Decrement => ValueType::Int64((integer(&value)? - 1).max(0)),
+            Transform::Boolean | Transform::NegatedBoolean => {
+                let boolean = match value.to_lowercase().as_str() {
+                    "yes" | "true" | "on" | "ask" => true,
+                    "no" | "false" | "off" => false,
+                    _ => return Ok(None),
+                };
+                ValueType::Bool(boolean != (self.transform == Some(Transform::NegatedBoolean)))
+            }
+        };
+        Ok(Some(value))
+    }
+}
+
+/// Mapping maps SSH options to their place in the Ansible inventory. Options without any rule
+/// are placed in `ansible_ssh_extra_args`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mapping {
+    rules: BTreeMap<Field, Rule>,
+}
+
+impl Default for Mapping {
+    fn default() -> Mapping {
+        Mapping::builtin(Connection::default())
+    }
+}
+
+impl Mapping {
+    /// The built-in mapping for the provided connection plugin, translating options with a
+    /// dedicated Ansible connection variable.
+    pub fn builtin(connection: Connection) -> Mapping {
+        match connection {
+            Connection::Ssh => Mapping::ssh(),
+            Connection::Paramiko => Mapping::paramiko(),
+        }
+    }
+
+    fn ssh() -> Mapping {
+        Mapping {
+            rules: BTreeMap::from([
+                (Field::HostName, Rule::host_var("ansible_host", None)),
+                (
+                    Field::Port,
+                    Rule::host_var("ansible_port", Some(Transform::Port)),
+                ),
+                (Field::User, Rule::host_var("ansible_user", None)),
+                (
+                    Field::IdentityFile,
+                    Rule::host_var("ansible_ssh_private_key_file", None),
+                ),
+                (
+                    Field::StrictHostKeyChecking,
+                    Rule::host_var("ansible_host_key_checking", Some(Transform::Boolean)),
+                ),
+                (
+                    Field::ConnectTimeout,
+                    Rule::host_var("ansible_ssh_timeout", Some(Transform::Integer)),
+                ),
+                (
+                    Field::ConnectionAttempts,
+                    Rule::host_var("ansible_ssh_retries", Some(Transform::Decrement)),
+                ),
+                (
+                    Field::ControlPath,
+                    Rule::host_var("ansible_control_path", Some(Transform::EscapePercent)),
+                ),
+                (Field::ControlPersist, Rule::placed(Placement::SshArgs)),
+                (
+                    Field::PasswordAuthentication,
+                    Rule::placed(Placement::SshArgs),
+                ),
+                (
+                    Field::PreferredAuthentications,
+                    Rule::placed(Placement::SshArgs),
+                ),
+                (Field::ProxyCommand, Rule::placed(Placement::CommonArgs)),
+            ]),
+        }
+    }
+
+    /// Paramiko ignores ssh arguments, hence options without any rule here being unsupported.
+    /// See also: https://docs.ansible.com/ansible/latest/collections/ansible/builtin/paramiko_ssh_connection.html
+    fn paramiko() -> Mapping {
+        Mapping {
+            rules: BTreeMap::from([
+                (Field::HostName, Rule::host_var("ansible_host", None)),
+                (
+                    Field::Port,
+                    Rule::host_var("ansible_port", Some(Transform::Port)),
+                ),
+                (Field::User, Rule::host_var("ansible_user", None)),
+                (
+                    Field::IdentityFile,
+                    Rule::host_var("ansible_ssh_private_key_file", None),
+                ),
+                (
+                    Field::IdentitiesOnly,
+                    Rule::host_var(
+                        "ansible_paramiko_look_for_keys",
+                        Some(Transform::NegatedBoolean),
+                    ),
+                ),
+                (
+                    Field::StrictHostKeyChecking,
+                    Rule::host_var(
+                        "ansible_paramiko_host_key_checking",
+                        Some(Transform::Boolean),
+                    ),
+                ),
+                (
+                    Field::ConnectTimeout,
+                    Rule::host_var("ansible_paramiko_timeout", Some(Transform::Integer)),
+                ),
+                (
+                    Field::ProxyCommand,
+                    Rule::host_var("ansible_paramiko_proxy_command", None),
+                ),
+            ]),
+        }
+    }
+
+    /// Load the rules of the provided YAML mapping file, keyed by SSH option, on top of the
+    /// built-in mapping for the provided connection plugin.
+    pub fn load(connection: Connection, reader: impl Read) -> Result<Mapping, AppError> {
+        let rules: BTreeMap<String, Rule> =
+            serde_yaml::from_reader(reader).map_err(|e| AppError::InvalidMapping(e.to_string()))?;
+        let mut mapping = Mapping::builtin(connection);
+        for (key, rule) in rules {
+            let field = Field::from_key(&key)
+                .ok_or_else(|| AppError::InvalidMapping(format!("unknown SSH option: {key}")))?;
+            match (rule.placement, &rule.variable) {
+                (Placement::HostVar, None) => {
+                    return Err(AppError::InvalidMapping(format!(
+                        "{key} is placed as a host variable, but no variable is provided"
+                    )))
+                }
+                (Placement::HostVar, Some(_)) | (_, None) => {}
+                (_, Some(_)) => {
+                    return Err(AppError::InvalidMapping(format!(
+                        "{key} is not placed as a host variable, but a variable is provided"
+                    )))
+                }
+            }
+            mapping.rules.insert(field, rule);
+        }
+        Ok(mapping)
+    }
+
+    /// The rule for the provided SSH option.
+    pub fn rule(&self, field: Field) -> Rule {
+        self.rules
+            .get(&field)
+            .cloned()
+            .unwrap_or_else(|| Rule::placed(Placement::ExtraArgs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connection, Mapping, Placement, Rule, Transform};
+    use crate::common::error::AppError;
+    use crate::core::ssh_config::{Field, SshConfig};
+    use crate::core::variables::ValueType;
+    use rstest::rstest;
+
+    #[test]
+    fn builtin_rules() {
+        // Given:
+        let mapping = Mapping::builtin(Connection::Ssh);
+
+        // Then:
+        assert_eq!(
+            mapping.rule(Field::Port),
+            Rule::host_var("ansible_port", Some(Transform::Port))
+        );
+        assert_eq!(
+            mapping.rule(Field::ControlPersist),
+            Rule::placed(Placement::SshArgs)
+        );
+        assert_eq!(
+            mapping.rule(Field::LogLevel),
+            Rule::placed(Placement::ExtraArgs)
+        );
+    }
+
+    #[test]
+    fn builtin_paramiko_rules() {
+        // Given:
+        let mapping = Mapping::builtin(Connection::Paramiko);
+
+        // Then:
+        assert_eq!(
+            mapping.rule(Field::ProxyCommand),
+            Rule::host_var("ansible_paramiko_proxy_command", None)
+        );
+        assert_eq!(
+            mapping.rule(Field::ControlPersist),
+            Rule::placed(Placement::ExtraArgs)
+        );
+    }
+
+    #[test]
+    fn load_mapping_on_top_of_builtin_rules() -> Result<(), AppError> {
+        // Given:
+        let yaml = r#"IdentityAgent:
+  placement: host-var
+  variable: ssh_agent_socket
+loglevel:
+  placement: drop
+ForwardAgent:
+  placement: common-args
+"#;
+
+        // When:
+        let mapping = Mapping::load(Connection::Ssh, yaml.as_bytes())?;
+
+        // Then:
+        assert_eq!(
+            mapping.rule(Field::IdentityAgent),
+            Rule::host_var("ssh_agent_socket", None)
+        );
+        assert_eq!(mapping.rule(Field::LogLevel), Rule::placed(Placement::Drop));
+        assert_eq!(
+            mapping.rule(Field::ForwardAgent),
+            Rule::placed(Placement::CommonArgs)
+        );
+        assert_eq!(
+            mapping.rule(Field::HostName),
+            Rule::host_var("ansible_host", None)
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::unknown_option(
+        "Unknown:\n  placement: drop\n",
+        "Invalid mapping file: unknown SSH option: Unknown"
+    )]
+    #[case::host_var_without_variable(
+        "User:\n  placement: host-var\n",
+        "Invalid mapping file: User is placed as a host variable, but no variable is provided"
+    )]
+    #[case::variable_without_host_var(
+        "User:\n  placement: drop\n  variable: login\n",
+        "Invalid mapping file: User is not placed as a host variable, but a variable is provided"
+    )]
+    fn load_invalid_mapping(#[case] yaml: &str, #[case] expected: &str) {
+        // When:
+        let result = Mapping::load(Connection::Ssh, yaml.as_bytes());
+        // Then:
+        assert_eq!(result.err().unwrap().to_string(), expected);
+    }
+
+    #[rstest]
+    // Given:
+    #[case::string(None, "FATAL", Some(ValueType::String("FATAL".to_string())))]
+    #[case::integer(Some(Transform::Integer), "30", Some(ValueType::Int64(30)))]
+    #[case::integer_zero(Some(Transform::Integer), "0", Some(ValueType::Int64(0)))]
+    #[case::port(Some(Transform::Port), "65535", Some(ValueType::Int64(65535)))]
+    #[case::decrement(Some(Transform::Decrement), "3", Some(ValueType::Int64(2)))]
+    #[case::decrement_zero(Some(Transform::Decrement), "0", Some(ValueType::Int64(0)))]
+    #[case::boolean_yes(Some(Transform::Boolean), "yes", Some(ValueType::Bool(true)))]
+    #[case::boolean_off(Some(Transform::Boolean), "off", Some(ValueType::Bool(false)))]
+    #[case::boolean_other(Some(Transform::Boolean), "accept-new", None)]
+    #[case::negated_boolean(Some(Transform::NegatedBoolean), "yes", Some(ValueType::Bool(false)))]
+    #[case::escape_percent(
+        Some(Transform::EscapePercent),
+        "%r@%h:%p",
+        Some(ValueType::String("%%r@%%h:%%p".to_string()))
+    )]
+    fn transform_value(
+        #[case] transform: Option<Transform>,
+        #[case] value: &str,
+        #[case] expected: Option<ValueType>,
+    ) -> Result<(), AppError> {
+        // When:
+        let rule = Rule::host_var("var", transform);
+        let value = rule.transform(
+            &SshConfig::new(),
+            Field::LogLevel,
+            value.to_string(),
+            ValueType::String,
+        )?;
+        // Then:
+        assert_eq!(value, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::negative(Transform::Integer, "-1")]
+    #[case::not_a_number(Transform::Decrement, "three")]
+    fn transform_invalid_value(#[case] transform: Transform, #[case] value: &str) {
+        // When:
+        let rule = Rule::host_var("var", Some(transform));
+        let result = rule.transform(
+            &SshConfig::new(),
+            Field::Port,
+            value.to_string(),
+            ValueType::String,
+        );
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            format!("Invalid SSH config for host \"*\". Reason: Port is not a non-negative integer: {value}")
+        );
+    }
+
+    #[rstest]
+    // Given:
+    #[case::zero("0")]
+    #[case::too_large("70000")]
+    #[case::negative("-22")]
+    fn transform_invalid_port(#[case] value: &str) {
+        // When:
+        let rule = Rule::host_var("ansible_port", Some(Transform::Port));
+        let result = rule.transform(
+            &SshConfig::new(),
+            Field::Port,
+            value.to_string(),
+            ValueType::String,
+        );
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            format!("Invalid SSH config for host \"*\". Reason: Port is not a port between 1 and 65535: {value}")
+        );
+    }
+}
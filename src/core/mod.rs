@@ -1,4 +1,17 @@
 pub mod ansible;
+pub mod compatibility;
+pub mod defaults;
+pub mod duplicates;
+pub mod grouping;
+pub mod mapping;
+pub mod naming;
+pub mod options;
 pub mod parser;
+pub mod paths;
+pub mod patterns;
+pub mod proxy_jump;
+pub mod quoting;
+pub mod ranges;
+pub mod rules;
 pub mod ssh_config;
 pub mod variables;
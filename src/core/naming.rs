@@ -0,0 +1,280 @@
+use crate::common::error::AppError;
+use crate::core::ssh_config::{Field, SshConfig};
+use clap::ValueEnum;
+use regex::Regex;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use tracing::warn;
+
+/// GroupNamePolicy controls what to do with group names breaking Ansible's naming rules.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GroupNamePolicy {
+    // Fail on invalid group names.
+    Reject,
+    // Replace invalid characters with underscores, and suffix reserved names with one.
+    Sanitise,
+    // Keep invalid group names, with a warning.
+    #[default]
+    Allow,
+}
+
+/// Groups Ansible always defines.
+const RESERVED_GROUP_NAMES: [&str; 2] = ["all", "ungrouped"];
+
+lazy_static! {
+    /// Like Ansible, only accept word characters, and no leading digit.
+    static ref INVALID_GROUP_NAME_CHARS: Regex = Regex::new(r"^\d|\W").unwrap();
+}
+
+/// Check the provided group name against Ansible's naming rules, and apply the provided policy if
+/// it breaks them.
+pub fn group_name(name: &str, policy: GroupNamePolicy) -> Result<String, AppError> {
+    let reason = if RESERVED_GROUP_NAMES.contains(&name) {
+        "reserved by Ansible"
+    } else if name.is_empty() || INVALID_GROUP_NAME_CHARS.is_match(name) {
+        "only letters, digits and underscores are allowed, and no leading digit"
+    } else {
+        return Ok(name.to_owned());
+    };
+    match policy {
+        GroupNamePolicy::Reject => Err(AppError::InvalidGroupName {
+            name: name.to_owned(),
+            reason: reason.to_owned(),
+        }),
+        GroupNamePolicy::Sanitise => {
+            let sanitised = sanitise(name);
+            warn!(
+                name,
+                sanitised, reason, "Sanitised invalid Ansible group name"
+            );
+            Ok(sanitised)
+        }
+        GroupNamePolicy::Allow => {
+            warn!(name, reason, "Invalid Ansible group name");
+            Ok(name.to_owned())
+        }
+    }
+}
+
+/// Sanitise the provided group name as per Ansible's naming rules, replacing invalid characters
+/// with underscores, and suffixing reserved names with one.
+pub fn sanitise(name: &str) -> String {
+    if RESERVED_GROUP_NAMES.contains(&name) || name.is_empty() {
+        format!("{name}_")
+    } else {
+        INVALID_GROUP_NAME_CHARS.replace_all(name, "_").into_owned()
+    }
+}
+
+/// Warn about the provided host name if Ansible cannot address it in host patterns, e.g. in
+/// `--limit`, as it contains characters with a special meaning in these patterns.
+pub fn check_host_name(name: &str) {
+    let is_ipv6 = name.parse::<Ipv6Addr>().is_ok();
+    if let Some(c) = name
+        .chars()
+        .find(|c| c.is_whitespace() || ",!&*?[]".contains(*c) || (*c == ':' && !is_ipv6))
+    {
+        warn!(name, character = %c, "Host name cannot be used in Ansible host patterns");
+    } else if name.starts_with('~') {
+        warn!(
+            name,
+            "Host name would be read as a regular expression in Ansible host patterns"
+        );
+    }
+}
+
+/// Placeholder of a host name template.
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Alias,
+    Environment,
+    Field(Field),
+}
+
+/// Template names hosts in the inventory, e.g. `{alias}.{environment}` or `{HostName}`, where
+/// `{alias}` is the `Host` alias, `{environment}` the inventory's environment, and any other
+/// placeholder the value of the SSH option of the same name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        Template {
+            segments: vec![Segment::Alias],
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Template, String> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in `{template}`"))?;
+            let placeholder = &rest[start + 1..start + end];
+            segments.push(match placeholder {
+                "alias" => Segment::Alias,
+                "environment" => Segment::Environment,
+                _ => Segment::Field(
+                    Field::from_key(placeholder)
+                        .ok_or_else(|| format!("unknown placeholder `{{{placeholder}}}`"))?,
+                ),
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+        Ok(Template { segments })
+    }
+}
+
+/// Naming names hosts in the inventory, by rendering a template, then rewriting the result with a
+/// regular expression, if any.
+#[derive(Clone, Debug, Default)]
+pub struct Naming {
+    pub template: Template,
+    pub rewrite: Option<(Regex, String)>,
+}
+
+impl Naming {
+    /// Name the provided host, of the provided environment, in the inventory.
+    pub fn name(&self, ssh_config: &SshConfig, environment: &str) -> Result<String, AppError> {
+        let mut name = String::new();
+        for segment in &self.template.segments {
+            match segment {
+                Segment::Literal(literal) => name.push_str(literal),
+                Segment::Alias => name.push_str(&ssh_config.host),
+                Segment::Environment => name.push_str(environment),
+                Segment::Field(Field::HostName) => name.push_str(ssh_config.host_name()),
+                Segment::Field(field) => {
+                    name.push_str(ssh_config.fields.get(field).ok_or_else(|| {
+                        AppError::InvalidSshConfig {
+                            host: ssh_config.host.to_owned(),
+                            reason: format!("{field} is required to name the host, but not set"),
+                        }
+                    })?)
+                }
+            }
+        }
+        if let Some((regex, replacement)) = &self.rewrite {
+            name = regex.replace(&name, replacement).into_owned();
+        }
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_name, GroupNamePolicy, Naming, Template};
+    use crate::common::error::AppError;
+    use crate::core::ssh_config::{Annotations, Field, SshConfig};
+    use indexmap::IndexMap;
+    use regex::Regex;
+    use rstest::rstest;
+    use std::str::FromStr;
+
+    #[rstest]
+    // Given:
+    #[case::alias("{alias}", None, "vagrant-web1")]
+    #[case::alias_and_environment("{alias}.{environment}", None, "vagrant-web1.staging")]
+    #[case::host_name("{hostname}", None, "10.0.0.1")]
+    #[case::literals("host-{User}-{Port}!", None, "host-vagrant-2222!")]
+    #[case::rewrite("{alias}", Some(("^vagrant-", "")), "web1")]
+    #[case::rewrite_with_groups("{alias}", Some(("^vagrant-(.*)$", "${1}.local")), "web1.local")]
+    fn name_host(
+        #[case] template: &str,
+        #[case] rewrite: Option<(&str, &str)>,
+        #[case] expected: &str,
+    ) -> Result<(), AppError> {
+        // Given:
+        let ssh_config = SshConfig {
+            host: "vagrant-web1".to_string(),
+            fields: IndexMap::from([
+                (Field::HostName, "10.0.0.1".to_string()),
+                (Field::User, "vagrant".to_string()),
+                (Field::Port, "2222".to_string()),
+            ]),
+            annotations: Annotations::default(),
+        };
+        let naming = Naming {
+            template: Template::from_str(template).unwrap(),
+            rewrite: rewrite
+                .map(|(regex, replacement)| (Regex::new(regex).unwrap(), replacement.to_string())),
+        };
+        // When:
+        let name = naming.name(&ssh_config, "staging")?;
+        // Then:
+        assert_eq!(name, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::unknown_placeholder("{foo}", "unknown placeholder `{foo}`")]
+    #[case::unclosed_placeholder("{alias", "unclosed placeholder in `{alias`")]
+    fn parse_invalid_template(#[case] template: &str, #[case] expected: &str) {
+        // When:
+        let result = Template::from_str(template);
+        // Then:
+        assert_eq!(result.err().unwrap(), expected);
+    }
+
+    #[test]
+    fn name_host_with_missing_field() {
+        // Given:
+        let naming = Naming {
+            template: Template::from_str("{User}").unwrap(),
+            rewrite: None,
+        };
+        // When:
+        let result = naming.name(&SshConfig::new(), "local");
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Invalid SSH config for host \"*\". Reason: User is required to name the host, but not set"
+        );
+    }
+
+    #[rstest]
+    // Given:
+    #[case::valid("prod_eu", GroupNamePolicy::Reject, Ok("prod_eu"))]
+    #[case::sanitised_dash("my-env", GroupNamePolicy::Sanitise, Ok("my_env"))]
+    #[case::sanitised_dot("prod.eu", GroupNamePolicy::Sanitise, Ok("prod_eu"))]
+    #[case::sanitised_leading_digit("1st", GroupNamePolicy::Sanitise, Ok("_st"))]
+    #[case::sanitised_reserved("all", GroupNamePolicy::Sanitise, Ok("all_"))]
+    #[case::allowed("my-env", GroupNamePolicy::Allow, Ok("my-env"))]
+    #[case::rejected(
+        "my-env",
+        GroupNamePolicy::Reject,
+        Err("Invalid Ansible group name \"my-env\". Reason: only letters, digits and underscores are allowed, and no leading digit")
+    )]
+    #[case::rejected_reserved(
+        "ungrouped",
+        GroupNamePolicy::Reject,
+        Err("Invalid Ansible group name \"ungrouped\". Reason: reserved by Ansible")
+    )]
+    fn check_group_name(
+        #[case] name: &str,
+        #[case] policy: GroupNamePolicy,
+        #[case] expected: Result<&str, &str>,
+    ) {
+        // When:
+        let result = group_name(name, policy);
+        // Then:
+        assert_eq!(
+            result.as_deref().map_err(|e| e.to_string()),
+            expected.map_err(str::to_string)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::core::defaults::DefaultsMode;
+use crate::core::duplicates::DuplicatesPolicy;
+use crate::core::grouping::GroupBy;
+use crate::core::mapping::{Connection, Mapping};
+use crate::core::naming::{GroupNamePolicy, Naming};
+use crate::core::paths::{Directories, PathsMode};
+use crate::core::proxy_jump::ProxyJumpMode;
+use crate::core::rules::Rules;
+use crate::core::ssh_config::Field;
+use crate::core::variables::HostVar;
+use clap::ValueEnum;
+use std::collections::BTreeSet;
+
+/// Order controls the order of groups, hosts, group vars and SSH options in the inventory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Order {
+    // Sort everything, for a deterministic output.
+    #[default]
+    Sorted,
+    // Follow the order of the SSH configuration and of the command line.
+    Source,
+}
+
+/// Options controls how SSH configurations are translated into an Ansible inventory.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// How to translate `ProxyJump`.
+    pub proxy_jump: ProxyJumpMode,
+
+    /// Whether to let Ansible template values read from the SSH configuration.
+    pub allow_templating: bool,
+
+    /// The Ansible connection plugin to target.
+    pub connection: Connection,
+
+    /// Where each SSH option lands in the Ansible inventory.
+    pub mapping: Mapping,
+
+    /// Rules adding hosts to groups, setting their vars, or changing their SSH options, if any.
+    pub rules: Option<Rules>,
+
+    /// Whether to explain which rules fired for each host on `stderr`.
+    pub explain_rules: bool,
+
+    /// Whether to emit SSH options as a structured `ssh_options` host variable, rather than a
+    /// `ansible_ssh_extra_args` string.
+    pub options_as_vars: bool,
+
+    /// Fraction of the hosts which must share the value of a variable for it to be hoisted into
+    /// the group's vars, if hoisting at all.
+    pub hoist_threshold: Option<f64>,
+
+    /// The order of groups, hosts, group vars and SSH options in the inventory.
+    pub order: Order,
+
+    /// How to name hosts in the inventory.
+    pub naming: Naming,
+
+    /// Whether to compact consecutively numbered hosts into range patterns, e.g. `web[01:40]`.
+    pub compact_ranges: bool,
+
+    /// What to group hosts by, in child groups of the environment's group.
+    pub group_by: Vec<GroupBy>,
+
+    /// What to do with group names breaking Ansible's naming rules.
+    pub group_name_policy: GroupNamePolicy,
+
+    /// What to do with different hosts connecting to the same endpoint.
+    pub duplicates: DuplicatesPolicy,
+
+    /// Whether hosts connecting as different users are different endpoints.
+    pub duplicates_by_user: bool,
+
+    /// Variables to set on the hosts whose alias matches their pattern.
+    pub host_vars: Vec<HostVar>,
+
+    /// Whether to describe hosts via a `description` variable, set to the comment right above
+    /// their `Host` line.
+    pub describe_hosts: bool,
+
+    /// Whether to leave SSH options to a sidecar SSH config, referenced via `-F`, rather than to
+    /// translate them into Ansible variables.
+    pub sidecar: bool,
+
+    /// Whether to connect to loopback hosts locally, rather than via SSH.
+    pub detect_local: bool,
+
+    /// How to emit values equal to OpenSSH's or Ansible's defaults.
+    pub defaults: DefaultsMode,
+
+    /// The user running s2a, which is OpenSSH's default user.
+    pub local_user: Option<String>,
+
+    /// How to rewrite the paths of the SSH configuration.
+    pub paths: PathsMode,
+
+    /// What the paths of the SSH configuration are resolved against.
+    pub directories: Directories,
+
+    /// SSH options to forward as-is, even though they are known to break Ansible.
+    pub keep_options: BTreeSet<Field>,
+}
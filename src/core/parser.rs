@@ -1,23 +1,44 @@
 use crate::common::error::AppError;
 use crate::core::ansible::Inventory;
+use crate::core::options::Options;
 use crate::core::ssh_config::SshConfig;
 use crate::core::variables::ValueType;
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, Write};
 use tracing::info;
 
-/// Parse the provided input SSH config, convert it to an Ansible YAML
-/// inventory, named after the provided environment, and write this YAML
-/// inventory to the provided output.
+/// Parse the provided input SSH config, apply the rules, if any, convert it
+/// to an Ansible YAML inventory, named after the provided environment, and write this YAML
+/// inventory to the provided output, and the normalised SSH config to the
+/// provided sidecar output, if any.
 pub fn parse_and_serialise_as_yaml(
     environment: &str,
     vars: &Option<Vec<(String, ValueType)>>,
+    options: &Options,
     input: &mut impl BufRead,
     output: &mut impl Write,
+    sidecar: Option<&mut dyn Write>,
 ) -> Result<(), AppError> {
-    let ssh_configs = SshConfig::parse(input)?;
+    let mut ssh_configs = if sidecar.is_some() {
+        // The sidecar SSH config must behave exactly as the provided one:
+        SshConfig::parse_strict(input)?
+    } else {
+        SshConfig::parse(input)?
+    };
     info!("Successfully parsed SSH config: {:?}", ssh_configs);
-    let inventory = Inventory::new(environment, &ssh_configs, vars);
+    if let Some(rules) = &options.rules {
+        let mut stderr = io::stderr();
+        let explanation = options
+            .explain_rules
+            .then_some(&mut stderr as &mut dyn Write);
+        rules.apply(&mut ssh_configs, explanation)?;
+        info!("Successfully applied rules: {:?}", ssh_configs);
+    }
+    let inventory = Inventory::new(environment, &ssh_configs, vars, options)?;
     info!("Successfully generated inventory: {:?}", inventory);
+    if let Some(mut sidecar) = sidecar {
+        SshConfig::write(inventory.ssh_configs(), options.order, &mut sidecar)?;
+        info!("Successfully wrote sidecar SSH config");
+    }
     serde_yaml::to_writer(output, &inventory)?;
     info!("Successfully serialised inventory as YAML",);
     Ok(())
@@ -30,6 +51,8 @@ mod tests {
     use crate::common::testing::utilities::{
         sample_ansible_inventory, sample_ansible_inventory_with_vars, SAMPLE_SSH_CONFIG,
     };
+    use crate::core::duplicates::DuplicatesPolicy;
+    use crate::core::options::Options;
     use crate::core::variables::ValueType;
     use std::io::BufWriter;
 
@@ -41,7 +64,14 @@ mod tests {
         let environment = "unit-test";
 
         // When:
-        parse_and_serialise_as_yaml(environment, &Option::None, &mut input, &mut output)?;
+        parse_and_serialise_as_yaml(
+            environment,
+            &Option::None,
+            &Options::default(),
+            &mut input,
+            &mut output,
+            None,
+        )?;
 
         // Then:
         let bytes = output.buffer();
@@ -68,7 +98,14 @@ mod tests {
         ]));
 
         // When:
-        parse_and_serialise_as_yaml(environment, &vars, &mut input, &mut output)?;
+        parse_and_serialise_as_yaml(
+            environment,
+            &vars,
+            &Options::default(),
+            &mut input,
+            &mut output,
+            None,
+        )?;
 
         // Then:
         let bytes = output.buffer();
@@ -77,4 +114,45 @@ mod tests {
         assert_eq!(yaml, sample_ansible_inventory_with_vars(environment));
         Ok(())
     }
+
+    #[test]
+    fn parse_ssh_config_and_write_sidecar_of_inventory_hosts() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host db1
+  HostName 10.0.0.1
+Host db1-staging
+  HostName 10.0.0.1
+Host web1
+  HostName 10.0.0.2
+"#
+        .as_bytes();
+        let mut output = Vec::new();
+        let mut sidecar = Vec::new();
+        let options = Options {
+            sidecar: true,
+            duplicates: DuplicatesPolicy::KeepFirst,
+            ..Options::default()
+        };
+
+        // When:
+        parse_and_serialise_as_yaml(
+            "prod",
+            &Option::None,
+            &options,
+            &mut input,
+            &mut output,
+            Some(&mut sidecar),
+        )?;
+
+        // Then:
+        assert_eq!(
+            String::from_utf8(sidecar)?,
+            r#"Host db1
+  HostName 10.0.0.1
+Host web1
+  HostName 10.0.0.2
+"#
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,314 @@
+use crate::core::ssh_config::Field;
+use crate::core::variables::{escape_jinja, INVENTORY_DIR};
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+/// PathsMode controls how the paths of the SSH configuration are rewritten.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PathsMode {
+    // Keep paths as written.
+    #[default]
+    AsIs,
+    // Rewrite paths as absolute paths.
+    Absolute,
+    // Rewrite paths relative to the output inventory's directory, i.e. Ansible's `{{ inventory_dir }}`.
+    RelativeToInventory,
+    // Rewrite paths under the home directory as `~/...`, and other paths as absolute paths.
+    HomeRelative,
+}
+
+/// SSH options holding paths.
+const PATH_FIELDS: [Field; 5] = [
+    Field::CertificateFile,
+    Field::ControlPath,
+    Field::IdentityAgent,
+    Field::IdentityFile,
+    Field::UserKnownHostsFile,
+];
+
+/// Directories tells what paths of the SSH configuration are resolved against.
+#[derive(Clone, Debug, Default)]
+pub struct Directories {
+    /// The working directory, which relative paths are resolved against.
+    pub working: PathBuf,
+    /// The home directory, which `~` stands for.
+    pub home: Option<PathBuf>,
+    /// The directory of the output inventory.
+    pub inventory: Option<PathBuf>,
+}
+
+impl Directories {
+    pub fn new(
+        working: PathBuf,
+        home: Option<PathBuf>,
+        output_filepath: Option<&Path>,
+    ) -> Directories {
+        let inventory = output_filepath
+            .map(|output_filepath| normalise(&working.join(output_filepath)))
+            .and_then(|output_filepath| output_filepath.parent().map(Path::to_path_buf));
+        Directories {
+            working,
+            home,
+            inventory,
+        }
+    }
+}
+
+/// Rewrite the paths held by the provided SSH options, as per the provided mode, and return the
+/// options whose values Ansible must then template, i.e. relative to `{{ inventory_dir }}`.
+/// Jinja delimiters of these paths are escaped, unless templating is allowed.
+pub fn rewrite(
+    fields: &mut IndexMap<Field, String>,
+    mode: PathsMode,
+    directories: &Directories,
+    allow_templating: bool,
+) -> HashSet<Field> {
+    let mut templated = HashSet::new();
+    if mode == PathsMode::AsIs {
+        return templated;
+    }
+    for field in PATH_FIELDS {
+        if let Some(value) = fields.get_mut(&field) {
+            let mut rewrite = |path: &str| {
+                let (path, is_templated) = rewrite_path(path, mode, directories, allow_templating);
+                if is_templated {
+                    templated.insert(field);
+                }
+                path
+            };
+            *value = if field == Field::UserKnownHostsFile {
+                // UserKnownHostsFile accepts several files, split like ssh does, i.e. on
+                // whitespace outside of quotes, and left as-is if its quotes are unbalanced:
+                match shlex::split(value) {
+                    Some(paths) => paths
+                        .iter()
+                        .map(|path| quote(&rewrite(path)))
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    None => value.to_owned(),
+                }
+            } else {
+                rewrite(value)
+            };
+        }
+    }
+    templated
+}
+
+/// Rewrite the provided path, as per the provided mode, and tell whether Ansible must template it.
+fn rewrite_path(
+    value: &str,
+    mode: PathsMode,
+    directories: &Directories,
+    allow_templating: bool,
+) -> (String, bool) {
+    let (path, quoted) = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(path) => (path, true),
+        None => (value, false),
+    };
+    let Some(absolute) = absolute(path, directories) else {
+        return (value.to_owned(), false);
+    };
+    let (rewritten, templated) = match (mode, &directories.home, &directories.inventory) {
+        (PathsMode::HomeRelative, Some(home), _) if absolute.starts_with(home) => {
+            let relative = relative(home, &absolute);
+            if relative.as_os_str().is_empty() {
+                ("~".to_string(), false)
+            } else {
+                (format!("~/{}", relative.display()), false)
+            }
+        }
+        (PathsMode::RelativeToInventory, _, Some(inventory)) => {
+            // Paths outside the inventory's tree, written from the root or home directory, are
+            // the same on any machine, unlike their path relative to the inventory:
+            if !absolute.starts_with(inventory)
+                && (path.starts_with('~') || Path::new(path).is_absolute())
+            {
+                return (value.to_owned(), false);
+            }
+            let relative = relative(inventory, &absolute);
+            if relative.as_os_str().is_empty() {
+                (INVENTORY_DIR.to_string(), true)
+            } else {
+                let relative = relative.display().to_string();
+                let relative = if allow_templating {
+                    relative
+                } else {
+                    escape_jinja(&relative).into_owned()
+                };
+                (format!("{INVENTORY_DIR}/{relative}"), true)
+            }
+        }
+        _ => (absolute.display().to_string(), false),
+    };
+    if quoted {
+        (format!("\"{rewritten}\""), templated)
+    } else {
+        (rewritten, templated)
+    }
+}
+
+/// Double-quote the provided path if ssh would otherwise split it or strip its quotes.
+fn quote(path: &str) -> String {
+    if path.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c)) {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Resolve the provided path into a normalised absolute path, unless this is not a path, e.g.
+/// `none`, or it can only be resolved by ssh, e.g. `~user/`, `%d/` or `${HOME}/`.
+fn absolute(path: &str, directories: &Directories) -> Option<PathBuf> {
+    if path.eq_ignore_ascii_case("none") || path == "SSH_AUTH_SOCK" || path.starts_with(['$', '%'])
+    {
+        return None;
+    }
+    let path = if path == "~" {
+        directories.home.clone()?
+    } else if let Some(path) = path.strip_prefix("~/") {
+        directories.home.as_ref()?.join(path)
+    } else if path.starts_with('~') {
+        return None;
+    } else {
+        directories.working.join(path) // Keeps absolute paths as-is.
+    };
+    Some(normalise(&path))
+}
+
+/// Lexically remove `.` and resolve `..` from the provided path.
+fn normalise(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalised.pop();
+            }
+            component => normalised.push(component),
+        }
+    }
+    normalised
+}
+
+/// The path leading from the provided directory to the provided path, both absolute.
+fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from = from.components().collect::<Vec<Component>>();
+    let to = to.components().collect::<Vec<Component>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rewrite, Directories, PathsMode};
+    use crate::core::ssh_config::Field;
+    use indexmap::IndexMap;
+    use rstest::rstest;
+    use std::path::{Path, PathBuf};
+
+    fn sample_directories() -> Directories {
+        Directories::new(
+            PathBuf::from("/home/alice/project"),
+            Some(PathBuf::from("/home/alice")),
+            Some(Path::new("inventory/../inventories/local.yaml")),
+        )
+    }
+
+    #[rstest]
+    // Given:
+    #[case::as_is(PathsMode::AsIs, "../keys/x", "../keys/x")]
+    #[case::absolute_relative(PathsMode::Absolute, "../keys/x", "/home/alice/keys/x")]
+    #[case::absolute_bare(PathsMode::Absolute, "id_ed25519", "/home/alice/project/id_ed25519")]
+    #[case::absolute_home(PathsMode::Absolute, "~/.vagrant/key", "/home/alice/.vagrant/key")]
+    #[case::absolute_quoted(PathsMode::Absolute, "\"~/my keys/x\"", "\"/home/alice/my keys/x\"")]
+    #[case::absolute_other_user(PathsMode::Absolute, "~bob/key", "~bob/key")]
+    #[case::absolute_token(PathsMode::Absolute, "%d/.ssh/key", "%d/.ssh/key")]
+    #[case::absolute_none(PathsMode::Absolute, "none", "none")]
+    #[case::home_relative(PathsMode::HomeRelative, "./key", "~/project/key")]
+    #[case::home_relative_outside(PathsMode::HomeRelative, "/etc/ssh/key", "/etc/ssh/key")]
+    #[case::relative_to_inventory(
+        PathsMode::RelativeToInventory,
+        "keys/x",
+        "{{ inventory_dir }}/../keys/x"
+    )]
+    #[case::relative_to_inventory_below(
+        PathsMode::RelativeToInventory,
+        "inventories/keys/x",
+        "{{ inventory_dir }}/keys/x"
+    )]
+    #[case::relative_to_inventory_absolute_below(
+        PathsMode::RelativeToInventory,
+        "/home/alice/project/inventories/keys/x",
+        "{{ inventory_dir }}/keys/x"
+    )]
+    #[case::relative_to_inventory_home(
+        PathsMode::RelativeToInventory,
+        "~/.ssh/cm-%C",
+        "~/.ssh/cm-%C"
+    )]
+    #[case::relative_to_inventory_outside(
+        PathsMode::RelativeToInventory,
+        "/etc/ssh/key",
+        "/etc/ssh/key"
+    )]
+    #[case::relative_to_inventory_expression(
+        PathsMode::RelativeToInventory,
+        "keys/{{x}}",
+        "{{ inventory_dir }}/{% raw %}../keys/{{x}}{% endraw %}"
+    )]
+    fn rewrite_identity_file(#[case] mode: PathsMode, #[case] path: &str, #[case] expected: &str) {
+        // When:
+        let mut fields = IndexMap::from([(Field::IdentityFile, path.to_string())]);
+        let templated = rewrite(&mut fields, mode, &sample_directories(), false);
+        // Then:
+        assert_eq!(fields[&Field::IdentityFile], expected);
+        assert_eq!(
+            templated.contains(&Field::IdentityFile),
+            expected.starts_with("{{ inventory_dir }}")
+        );
+    }
+
+    #[test]
+    fn rewrite_user_known_hosts_files() {
+        // Given:
+        let mut fields = IndexMap::from([
+            (
+                Field::UserKnownHostsFile,
+                "known_hosts \"my dir/known\" /dev/null".to_string(),
+            ),
+            (Field::LogLevel, "./FATAL".to_string()),
+        ]);
+
+        // When:
+        let templated = rewrite(
+            &mut fields,
+            PathsMode::Absolute,
+            &sample_directories(),
+            false,
+        );
+
+        // Then:
+        assert_eq!(
+            fields,
+            IndexMap::from([
+                (
+                    Field::UserKnownHostsFile,
+                    "/home/alice/project/known_hosts \"/home/alice/project/my dir/known\" /dev/null"
+                        .to_string()
+                ),
+                (Field::LogLevel, "./FATAL".to_string()),
+            ])
+        );
+        assert!(templated.is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use regex::Regex;
+
+/// Compile the provided glob pattern, as in `Host` lines, where `*` matches any characters and `?`
+/// exactly one character.
+pub fn glob(pattern: &str) -> Regex {
+    let regex = pattern
+        .chars()
+        .map(|c| match c {
+            '*' => ".*".to_string(),
+            '?' => ".".to_string(),
+            c => regex::escape(&c.to_string()),
+        })
+        .collect::<String>();
+    Regex::new(&format!("^{regex}$")).unwrap() // Every other character is escaped.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob;
+    use rstest::rstest;
+
+    #[rstest]
+    // Given:
+    #[case::exact("web1", "web1", true)]
+    #[case::star("web*", "web12", true)]
+    #[case::question_mark("web?", "web12", false)]
+    #[case::escaped("web.1", "webx1", false)]
+    fn match_glob(#[case] pattern: &str, #[case] name: &str, #[case] expected: bool) {
+        // When:
+        let matches = glob(pattern).is_match(name);
+        // Then:
+        assert_eq!(matches, expected);
+    }
+}
@@ -0,0 +1,413 @@
+use crate::common::error::AppError;
+use crate::core::quoting;
+use crate::core::ssh_config::{Field, SshConfig};
+use clap::ValueEnum;
+use std::net::IpAddr;
+use tracing::{debug, warn};
+
+/// ProxyJumpMode controls how `ProxyJump` is translated into the Ansible inventory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProxyJumpMode {
+    // Keep `ProxyJump` as-is, which requires the Ansible control node to have the same SSH configuration.
+    #[default]
+    AsIs,
+    // Resolve jump hosts and emit a self-contained `ProxyCommand` in `ansible_ssh_common_args`.
+    ProxyCommand,
+    // Resolve jump hosts and emit an inline `user@host:port` `ProxyJump` chain.
+    Inline,
+}
+
+/// Hop is a single jump host, resolved against the parsed SSH configurations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hop {
+    pub host: String,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<String>,
+}
+
+impl Hop {
+    /// Format this hop as a `[user@]host[:port]` destination, as expected by `ProxyJump`.
+    pub fn destination(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host) // IPv6 address.
+        } else {
+            self.host.to_owned()
+        };
+        match (&self.user, self.port) {
+            (Some(user), Some(port)) => format!("{user}@{host}:{port}"),
+            (Some(user), None) => format!("{user}@{host}"),
+            (None, Some(port)) => format!("{host}:{port}"),
+            (None, None) => host,
+        }
+    }
+
+    /// Build the `ssh` command forwarding stdin and stdout to the final destination through this
+    /// hop, itself optionally reached through the provided `ProxyCommand`.
+    fn ssh_command(&self, proxy_command: Option<&str>) -> String {
+        let mut args = Vec::from(["ssh".to_string(), "-W".to_string(), "%h:%p".to_string()]);
+        if let Some(proxy_command) = proxy_command {
+            // ssh expands %-tokens in the outer ProxyCommand, hence escaping them in the inner one:
+            args.push("-o".to_string());
+            args.push(format!("ProxyCommand={}", proxy_command.replace('%', "%%")));
+        }
+        if let Some(identity_file) = &self.identity_file {
+            args.push("-i".to_string());
+            args.push(identity_file.to_owned());
+        }
+        if let Some(port) = self.port {
+            args.push("-p".to_string());
+            args.push(port.to_string());
+        }
+        if let Some(user) = &self.user {
+            args.push("-l".to_string());
+            args.push(user.to_owned());
+        }
+        args.push(self.host.to_owned());
+        quoting::join(args)
+    }
+}
+
+/// Format the provided hops as an inline `ProxyJump` chain, e.g. `user@bastion:2222,gateway`.
+pub fn inline(hops: &[Hop]) -> String {
+    if hops.iter().any(|hop| hop.identity_file.is_some()) {
+        warn!("Inline ProxyJump chains cannot carry the IdentityFile of jump hosts, use --proxy-jump=proxy-command to keep them");
+    }
+    hops.iter()
+        .map(Hop::destination)
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Format the provided hops as a self-contained `ProxyCommand`, nesting one `ssh -W` per hop.
+pub fn proxy_command(hops: &[Hop]) -> String {
+    hops.iter()
+        .fold(None, |proxy_command: Option<String>, hop| {
+            Some(hop.ssh_command(proxy_command.as_deref()))
+        })
+        .unwrap_or_default()
+}
+
+/// Resolver resolves `ProxyJump` chains against the parsed SSH configurations.
+pub struct Resolver<'a> {
+    ssh_configs: &'a [SshConfig],
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(ssh_configs: &'a [SshConfig]) -> Resolver<'a> {
+        Resolver { ssh_configs }
+    }
+
+    /// Resolve the hops to go through in order to reach the provided host, if any.
+    pub fn resolve(&self, ssh_config: &SshConfig) -> Result<Option<Vec<Hop>>, AppError> {
+        let Some(proxy_jump) = ssh_config.fields.get(&Field::ProxyJump) else {
+            return Ok(None);
+        };
+        if proxy_jump.eq_ignore_ascii_case("none") {
+            return Ok(None);
+        }
+        if ssh_config.fields.contains_key(&Field::ProxyCommand) {
+            // ssh uses whichever of ProxyCommand and ProxyJump comes first, which we cannot tell.
+            warn!(
+                host = ssh_config.host,
+                "Both ProxyCommand and ProxyJump are set, ignoring ProxyJump"
+            );
+            return Ok(None);
+        }
+        let mut path = Vec::from([ssh_config.host.to_owned()]);
+        let hops = self.resolve_chain(&ssh_config.host, proxy_jump, &mut path)?;
+        debug!(host = ssh_config.host, "Resolved ProxyJump: {:?}", hops);
+        Ok(Some(hops))
+    }
+
+    /// Resolve a comma-separated list of jump hosts. Like ssh, only the first jump host's own
+    /// `ProxyJump` is followed, as ssh connects to subsequent ones through the previous hop.
+    fn resolve_chain(
+        &self,
+        host: &str,
+        proxy_jump: &str,
+        path: &mut Vec<String>,
+    ) -> Result<Vec<Hop>, AppError> {
+        let mut hops = Vec::new();
+        for (i, destination) in proxy_jump.split(',').map(str::trim).enumerate() {
+            let (user, jump_host, port) = parse_destination(host, destination)?;
+            match self.find(jump_host) {
+                Some(jump_config) => {
+                    if path.contains(&jump_config.host) {
+                        path.push(jump_config.host.to_owned());
+                        return Err(AppError::ProxyJumpCycle {
+                            host: path[0].to_owned(),
+                            cycle: path.join(" -> "),
+                        });
+                    }
+                    if i == 0 {
+                        if let Some(own_proxy_jump) = jump_config.fields.get(&Field::ProxyJump) {
+                            if !own_proxy_jump.eq_ignore_ascii_case("none") {
+                                path.push(jump_config.host.to_owned());
+                                hops.extend(self.resolve_chain(
+                                    &jump_config.host,
+                                    own_proxy_jump,
+                                    path,
+                                )?);
+                                path.pop();
+                            }
+                        }
+                    }
+                    hops.push(Hop {
+                        host: jump_config
+                            .fields
+                            .get(&Field::HostName)
+                            .map_or(jump_host, String::as_str)
+                            .to_owned(),
+                        user: user.or_else(|| jump_config.fields.get(&Field::User).cloned()),
+                        port: match port {
+                            Some(port) => Some(port),
+                            None => jump_config
+                                .fields
+                                .get(&Field::Port)
+                                .map(|port| parse_port(&jump_config.host, port))
+                                .transpose()?,
+                        },
+                        identity_file: jump_config.fields.get(&Field::IdentityFile).cloned(),
+                    });
+                }
+                None if is_hostname(jump_host) => hops.push(Hop {
+                    host: jump_host.to_owned(),
+                    user,
+                    port,
+                    identity_file: None,
+                }),
+                None => {
+                    return Err(AppError::UnknownJumpHost {
+                        host: host.to_owned(),
+                        jump_host: jump_host.to_owned(),
+                    })
+                }
+            }
+        }
+        Ok(hops)
+    }
+
+    fn find(&self, alias: &str) -> Option<&'a SshConfig> {
+        self.ssh_configs
+            .iter()
+            .find(|ssh_config| ssh_config.host.split_whitespace().any(|name| name == alias))
+    }
+}
+
+/// Parse a `[ssh://][user@]host[:port]` destination into its components.
+fn parse_destination<'d>(
+    host: &str,
+    destination: &'d str,
+) -> Result<(Option<String>, &'d str, Option<u16>), AppError> {
+    let destination = destination.strip_prefix("ssh://").unwrap_or(destination);
+    let (user, rest) = match destination.rsplit_once('@') {
+        Some((user, rest)) => (Some(user.to_owned()), rest),
+        None => (None, destination),
+    };
+    let (jump_host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+        match bracketed.split_once(']') {
+            Some((ipv6, port)) => (ipv6, port.strip_prefix(':')),
+            None => (rest, None),
+        }
+    } else {
+        match rest.split_once(':') {
+            Some((jump_host, port)) if !port.contains(':') => (jump_host, Some(port)),
+            _ => (rest, None),
+        }
+    };
+    let port = port.map(|port| parse_port(host, port)).transpose()?;
+    Ok((user, jump_host, port))
+}
+
+fn parse_port(host: &str, port: &str) -> Result<u16, AppError> {
+    port.parse::<u16>().map_err(|_| AppError::InvalidSshConfig {
+        host: host.to_owned(),
+        reason: format!("invalid port: {port}"),
+    })
+}
+
+/// Tell if the provided jump host is usable without any SSH configuration, i.e. is a fully
+/// qualified domain name or an IP address, rather than an alias only ssh knows about.
+fn is_hostname(jump_host: &str) -> bool {
+    jump_host.contains('.') || jump_host.parse::<IpAddr>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inline, proxy_command, Hop, Resolver};
+    use crate::common::error::AppError;
+    use crate::core::ssh_config::{Annotations, Field, SshConfig};
+    use indexmap::IndexMap;
+
+    fn ssh_config(host: &str, fields: &[(Field, &str)]) -> SshConfig {
+        SshConfig {
+            host: host.to_string(),
+            fields: fields
+                .iter()
+                .map(|(field, value)| (*field, value.to_string()))
+                .collect::<IndexMap<Field, String>>(),
+            annotations: Annotations::default(),
+        }
+    }
+
+    fn sample_ssh_configs() -> Vec<SshConfig> {
+        Vec::from([
+            ssh_config(
+                "bastion",
+                &[
+                    (Field::HostName, "bastion.example.com"),
+                    (Field::User, "jump"),
+                    (Field::Port, "2222"),
+                    (Field::IdentityFile, "/keys/bastion"),
+                ],
+            ),
+            ssh_config(
+                "gateway",
+                &[(Field::HostName, "10.0.0.1"), (Field::ProxyJump, "bastion")],
+            ),
+            ssh_config(
+                "app",
+                &[(Field::HostName, "10.0.1.1"), (Field::ProxyJump, "gateway")],
+            ),
+        ])
+    }
+
+    #[test]
+    fn resolve_chain_through_other_hosts() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = sample_ssh_configs();
+        let resolver = Resolver::new(&ssh_configs);
+
+        // When:
+        let hops = resolver.resolve(&ssh_configs[2])?;
+
+        // Then:
+        assert_eq!(
+            hops,
+            Some(Vec::from([
+                Hop {
+                    host: "bastion.example.com".to_string(),
+                    user: Some("jump".to_string()),
+                    port: Some(2222),
+                    identity_file: Some("/keys/bastion".to_string()),
+                },
+                Hop {
+                    host: "10.0.0.1".to_string(),
+                    user: None,
+                    port: None,
+                    identity_file: None,
+                },
+            ]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_inline_destination_overrides_jump_host_config() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([
+            ssh_config("bastion", &[(Field::HostName, "bastion.example.com")]),
+            ssh_config(
+                "app",
+                &[(Field::ProxyJump, "admin@bastion:22,me@[fe80::1]:2022")],
+            ),
+        ]);
+        let resolver = Resolver::new(&ssh_configs);
+
+        // When:
+        let hops = resolver.resolve(&ssh_configs[1])?.unwrap();
+
+        // Then:
+        assert_eq!(
+            inline(&hops),
+            "admin@bastion.example.com:22,me@[fe80::1]:2022"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_none() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = Vec::from([ssh_config("app", &[(Field::ProxyJump, "none")])]);
+        let resolver = Resolver::new(&ssh_configs);
+
+        // When:
+        let hops = resolver.resolve(&ssh_configs[0])?;
+
+        // Then:
+        assert_eq!(hops, None);
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_unknown_jump_host() {
+        // Given:
+        let ssh_configs = Vec::from([ssh_config("app", &[(Field::ProxyJump, "bastion")])]);
+        let resolver = Resolver::new(&ssh_configs);
+
+        // When:
+        let result = resolver.resolve(&ssh_configs[0]);
+
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Unknown jump host \"bastion\" in ProxyJump of host \"app\""
+        );
+    }
+
+    #[test]
+    fn resolve_cycle() {
+        // Given:
+        let ssh_configs = Vec::from([
+            ssh_config("a", &[(Field::ProxyJump, "b")]),
+            ssh_config("b", &[(Field::ProxyJump, "c")]),
+            ssh_config("c", &[(Field::ProxyJump, "a")]),
+        ]);
+        let resolver = Resolver::new(&ssh_configs);
+
+        // When:
+        let result = resolver.resolve(&ssh_configs[0]);
+
+        // Then:
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Cycle in ProxyJump chain of host \"a\": a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn format_inline() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = sample_ssh_configs();
+        let hops = Resolver::new(&ssh_configs)
+            .resolve(&ssh_configs[2])?
+            .unwrap();
+
+        // When:
+        let proxy_jump = inline(&hops);
+
+        // Then:
+        assert_eq!(proxy_jump, "jump@bastion.example.com:2222,10.0.0.1");
+        Ok(())
+    }
+
+    #[test]
+    fn format_proxy_command() -> Result<(), AppError> {
+        // Given:
+        let ssh_configs = sample_ssh_configs();
+        let hops = Resolver::new(&ssh_configs)
+            .resolve(&ssh_configs[2])?
+            .unwrap();
+
+        // When:
+        let command = proxy_command(&hops);
+
+        // Then:
+        assert_eq!(
+            command,
+            "ssh -W %h:%p -o 'ProxyCommand=ssh -W %%h:%%p -i /keys/bastion -p 2222 -l jump bastion.example.com' 10.0.0.1"
+        );
+        Ok(())
+    }
+}
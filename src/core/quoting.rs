@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+
+/// Quote the provided argument so that a POSIX shell, or Python's `shlex.split` as used by
+/// Ansible, reads it back as a single and unaltered argument.
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        Cow::Borrowed(arg)
+    } else {
+        // Single quotes preserve everything but single quotes themselves, which therefore need to
+        // be closed, double-quoted, and re-opened:
+        Cow::Owned(format!("'{}'", arg.replace('\'', r#"'"'"'"#)))
+    }
+}
+
+/// Quote each of the provided arguments and join them with spaces, so that Ansible splits the
+/// resulting string back into exactly the provided arguments.
+pub fn join<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| quote(arg.as_ref()).into_owned())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Characters which never need quoting, same as Python's `shlex.quote`.
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join, quote};
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    #[rstest]
+    // Given:
+    #[case::safe("IdentitiesOnly=yes", "IdentitiesOnly=yes")]
+    #[case::empty("", "''")]
+    #[case::space("ssh -W %h:%p", "'ssh -W %h:%p'")]
+    #[case::dollar("$HOME", "'$HOME'")]
+    #[case::single_quote("it's", r#"'it'"'"'s'"#)]
+    fn quote_arg(#[case] input: &str, #[case] expected: &str) {
+        // When:
+        let quoted = quote(input);
+        // Then:
+        assert_eq!(quoted, expected);
+    }
+
+    #[test]
+    fn join_args() {
+        // Given:
+        let args = [
+            "-o",
+            "UserKnownHostsFile=\"/a b/known\"",
+            "-o",
+            "SetEnv=FOO=\"x y\"",
+        ];
+        // When:
+        let joined = join(args);
+        // Then:
+        assert_eq!(
+            joined,
+            r#"-o 'UserKnownHostsFile="/a b/known"' -o 'SetEnv=FOO="x y"'"#
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn shlex_split_of_joined_args_is_args(args in prop::collection::vec("[^\u{0}]*", 0..8)) {
+            // When:
+            let joined = join(&args);
+            // Then:
+            prop_assert_eq!(shlex::split(&joined), Some(args));
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use crate::core::variables::ValueType;
+use indexmap::IndexMap;
+use regex::Regex;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+lazy_static! {
+    /// The last run of digits in a host name, e.g. `01` in `web01.staging`.
+    static ref NUMBERED: Regex = Regex::new(r"^(?<prefix>.*?)(?<number>\d+)(?<suffix>\D*)$").unwrap();
+}
+
+const ANSIBLE_HOST: &str = "ansible_host";
+
+/// A host name, split around its last run of digits.
+#[derive(Clone, Copy)]
+struct Numbered<'a> {
+    /// The position of the host in the inventory.
+    position: usize,
+    name: &'a str,
+    prefix: &'a str,
+    token: &'a str,
+    number: u64,
+    suffix: &'a str,
+}
+
+impl<'a> Numbered<'a> {
+    fn new(position: usize, name: &'a str) -> Option<Numbered<'a>> {
+        // Ansible would read these characters as part of a range or port:
+        if name.contains(['[', ']', ':']) {
+            return None;
+        }
+        let captures = NUMBERED.captures(name)?;
+        let token = captures.name("number")?.as_str();
+        Some(Numbered {
+            position,
+            name,
+            prefix: captures.name("prefix")?.as_str(),
+            token,
+            number: token.parse().ok()?,
+            suffix: captures.name("suffix")?.as_str(),
+        })
+    }
+
+    /// Whether the number is zero-padded, e.g. `01`, which Ansible then pads every number of a
+    /// range to the same width.
+    fn is_padded(&self) -> bool {
+        self.token.len() > 1 && self.token.starts_with('0')
+    }
+}
+
+/// What `ansible_host` is, across a run of numbered hosts.
+#[derive(Clone, Debug, PartialEq)]
+enum HostTemplate {
+    Absent,
+    Literal(ValueType),
+    /// The host's own number, between the provided prefix and suffix.
+    Numbered {
+        before: String,
+        after: String,
+    },
+}
+
+impl HostTemplate {
+    /// The possible templates for the provided host's `ansible_host`.
+    fn candidates(host: &Numbered, ansible_host: Option<&ValueType>) -> Vec<HostTemplate> {
+        let Some(value) = ansible_host else {
+            return Vec::from([HostTemplate::Absent]);
+        };
+        let mut candidates = Vec::from([HostTemplate::Literal(value.clone())]);
+        if let ValueType::String(value) = value {
+            for (start, _) in value.match_indices(host.token) {
+                let (before, after) = (&value[..start], &value[start + host.token.len()..]);
+                // Only whole numbers, e.g. not `01` in `101`:
+                if !before.ends_with(|c: char| c.is_ascii_digit())
+                    && !after.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    candidates.push(HostTemplate::Numbered {
+                        before: before.to_owned(),
+                        after: after.to_owned(),
+                    });
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Render this template as the `ansible_host` of a range of hosts, with the provided prefix
+    /// and suffix around their number.
+    fn render(self, prefix: &str, suffix: &str) -> Option<ValueType> {
+        match self {
+            HostTemplate::Absent => None,
+            HostTemplate::Literal(value) => Some(value),
+            HostTemplate::Numbered { before, after } => {
+                // Keep the host name's own prefix and suffix, if alike, and replace them otherwise:
+                let (before, start) = match before == prefix {
+                    true => (None, 0),
+                    false => (Some(jinja_string(&before)), prefix.chars().count()),
+                };
+                let (after, end) = match after == suffix {
+                    true => (None, 0),
+                    false => (Some(jinja_string(&after)), suffix.chars().count()),
+                };
+                let number = match (start, end) {
+                    (0, 0) => "inventory_hostname".to_string(),
+                    (start, 0) => format!("inventory_hostname[{start}:]"),
+                    (start, end) => format!("inventory_hostname[{start}:-{end}]"),
+                };
+                Some(ValueType::String(format!(
+                    "{{{{ {} }}}}",
+                    [before, Some(number), after]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<String>>()
+                        .join(" ~ ")
+                )))
+            }
+        }
+    }
+}
+
+/// Quote the provided text as a Jinja string literal.
+fn jinja_string(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// A run of consecutively numbered hosts, with the same variables.
+struct Run<'a> {
+    hosts: Vec<Numbered<'a>>,
+    vars: BTreeMap<String, ValueType>,
+    templates: Vec<HostTemplate>,
+}
+
+impl<'a> Run<'a> {
+    fn new(host: Numbered<'a>, mut vars: BTreeMap<String, ValueType>) -> Run<'a> {
+        let ansible_host = vars.remove(ANSIBLE_HOST);
+        let templates = HostTemplate::candidates(&host, ansible_host.as_ref());
+        Run {
+            hosts: Vec::from([host]),
+            vars,
+            templates,
+        }
+    }
+
+    /// Extend this run with the provided host, if Ansible can still expand the resulting range
+    /// into the same hosts, with the same variables.
+    fn extend(&mut self, host: Numbered<'a>, vars: &BTreeMap<String, ValueType>) -> bool {
+        let first = &self.hosts[0];
+        let last = &self.hosts[self.hosts.len() - 1];
+        let is_formatted_alike = if first.is_padded() {
+            host.token.len() == first.token.len()
+        } else {
+            !host.is_padded()
+        };
+        let is_alike = host.number == last.number + 1
+            && is_formatted_alike
+            && vars.len() - usize::from(vars.contains_key(ANSIBLE_HOST)) == self.vars.len()
+            && self
+                .vars
+                .iter()
+                .all(|(name, value)| vars.get(name) == Some(value));
+        if !is_alike {
+            return false;
+        }
+        let candidates = HostTemplate::candidates(&host, vars.get(ANSIBLE_HOST));
+        let templates = self
+            .templates
+            .iter()
+            .filter(|template| candidates.contains(template))
+            .cloned()
+            .collect::<Vec<HostTemplate>>();
+        if templates.is_empty() {
+            return false;
+        }
+        self.templates = templates;
+        self.hosts.push(host);
+        true
+    }
+
+    /// The range pattern of this run, and its variables.
+    fn into_range(self) -> (String, BTreeMap<String, ValueType>) {
+        let first = &self.hosts[0];
+        let last = &self.hosts[self.hosts.len() - 1];
+        let pattern = format!(
+            "{}[{}:{}]{}",
+            first.prefix, first.token, last.token, first.suffix
+        );
+        let mut vars = self.vars;
+        let template = self.templates.into_iter().next().expect("a template");
+        if let Some(value) = template.render(first.prefix, first.suffix) {
+            vars.insert(ANSIBLE_HOST.to_string(), value);
+        }
+        (pattern, vars)
+    }
+}
+
+/// Compact runs of consecutively numbered hosts, e.g. `web01` to `web40`, into Ansible range
+/// patterns, e.g. `web[01:40]`, provided they have the same variables, apart from an
+/// `ansible_host` following the same numbering, e.g. `web01.example.com`. Hosts are numbered by
+/// the last run of digits in their name. Ranges take the place of their first host.
+pub fn compact(
+    hosts: IndexMap<String, BTreeMap<String, ValueType>>,
+) -> IndexMap<String, BTreeMap<String, ValueType>> {
+    let mut series = BTreeMap::<(&str, &str), Vec<(Numbered, &BTreeMap<String, ValueType>)>>::new();
+    let mut compacted = Vec::with_capacity(hosts.len());
+    for (position, (name, vars)) in hosts.iter().enumerate() {
+        match Numbered::new(position, name) {
+            Some(host) => series
+                .entry((host.prefix, host.suffix))
+                .or_default()
+                .push((host, vars)),
+            None => compacted.push((position, name.to_owned(), vars.clone())),
+        }
+    }
+    for mut numbered in series.into_values() {
+        numbered.sort_by_key(|(host, _)| host.number);
+        let mut runs: Vec<Run> = Vec::new();
+        for (host, vars) in numbered {
+            let is_extended = runs.last_mut().is_some_and(|run| run.extend(host, vars));
+            if !is_extended {
+                runs.push(Run::new(host, vars.clone()));
+            }
+        }
+        for run in runs {
+            let position = run.hosts.iter().map(|host| host.position).min();
+            let position = position.expect("at least one host");
+            if run.hosts.len() < 2 {
+                let name = run.hosts[0].name;
+                compacted.push((position, name.to_owned(), hosts[name].clone()));
+                continue;
+            }
+            let (pattern, vars) = run.into_range();
+            debug!(pattern, "Compacted numbered hosts into a range");
+            compacted.push((position, pattern, vars));
+        }
+    }
+    compacted.sort_by_key(|(position, _, _)| *position);
+    compacted
+        .into_iter()
+        .map(|(_, name, vars)| (name, vars))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compact;
+    use crate::core::variables::ValueType;
+    use indexmap::IndexMap;
+    use proptest::prelude::*;
+    use regex::Regex;
+    use rstest::rstest;
+    use std::collections::BTreeMap;
+
+    type Hosts = IndexMap<String, BTreeMap<String, ValueType>>;
+
+    /// Expand range patterns like Ansible's YAML inventory does, and render `ansible_host`
+    /// templates like Jinja would, for the templates compaction produces.
+    fn expand(hosts: &Hosts) -> Hosts {
+        let range = Regex::new(r"^(.*)\[(\d+):(\d+)\](.*)$").unwrap();
+        let template = Regex::new(
+            r"^\{\{ (?:'(?<before>[^']*)' ~ )?inventory_hostname(?:\[(?<start>\d+):(?:-(?<end>\d+))?\])?(?: ~ '(?<after>[^']*)')? \}\}$",
+        )
+        .unwrap();
+        let mut expanded = Hosts::new();
+        for (name, vars) in hosts {
+            let Some(captures) = range.captures(name) else {
+                expanded.insert(name.to_owned(), vars.clone());
+                continue;
+            };
+            let (prefix, begin, end, suffix) =
+                (&captures[1], &captures[2], &captures[3], &captures[4]);
+            let width = if begin.len() > 1 && begin.starts_with('0') {
+                assert_eq!(begin.len(), end.len());
+                begin.len()
+            } else {
+                0
+            };
+            for number in begin.parse::<u64>().unwrap()..=end.parse::<u64>().unwrap() {
+                let name = format!("{prefix}{number:0width$}{suffix}");
+                let mut vars = vars.clone();
+                if let Some(ValueType::String(value)) = vars.get("ansible_host") {
+                    if let Some(captures) = template.captures(value) {
+                        let chars = name.chars().collect::<Vec<char>>();
+                        let start = captures
+                            .name("start")
+                            .map_or(0, |m| m.as_str().parse().unwrap());
+                        let end = captures
+                            .name("end")
+                            .map_or(0, |m| m.as_str().parse().unwrap());
+                        let value = format!(
+                            "{}{}{}",
+                            captures.name("before").map_or("", |m| m.as_str()),
+                            chars[start..chars.len() - end].iter().collect::<String>(),
+                            captures.name("after").map_or("", |m| m.as_str()),
+                        );
+                        vars.insert("ansible_host".to_string(), ValueType::String(value));
+                    }
+                }
+                expanded.insert(name, vars);
+            }
+        }
+        expanded
+    }
+
+    fn sample_hosts(hosts: &[(&str, Option<(&str, &str)>)]) -> Hosts {
+        hosts
+            .iter()
+            .map(|(name, var)| {
+                (
+                    name.to_string(),
+                    var.iter()
+                        .map(|(name, value)| {
+                            (name.to_string(), ValueType::String(value.to_string()))
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[rstest]
+    // Given:
+    #[case::padded(
+        &[("web01", None), ("web02", None), ("web03", None)],
+        &[("web[01:03]", None)]
+    )]
+    #[case::padded_across_widths(
+        &[("web09", None), ("web10", None), ("web11", None)],
+        &[("web[09:11]", None)]
+    )]
+    #[case::natural_across_widths(
+        &[("web9", None), ("web10", None)],
+        &[("web[9:10]", None)]
+    )]
+    #[case::gap(
+        &[("web1", None), ("web2", None), ("web4", None)],
+        &[("web4", None), ("web[1:2]", None)]
+    )]
+    #[case::different_vars(
+        &[("web1", Some(("ansible_user", "a"))), ("web2", Some(("ansible_user", "b")))],
+        &[("web1", Some(("ansible_user", "a"))), ("web2", Some(("ansible_user", "b")))]
+    )]
+    #[case::suffix(
+        &[("web1.staging", Some(("ansible_port", "2222"))), ("web2.staging", Some(("ansible_port", "2222")))],
+        &[("web[1:2].staging", Some(("ansible_port", "2222")))]
+    )]
+    #[case::same_host(
+        &[("web1", Some(("ansible_host", "lb"))), ("web2", Some(("ansible_host", "lb")))],
+        &[("web[1:2]", Some(("ansible_host", "lb")))]
+    )]
+    #[case::numbered_host(
+        &[("web1", Some(("ansible_host", "node1.dc1.example.com"))), ("web2", Some(("ansible_host", "node2.dc1.example.com")))],
+        &[("web[1:2]", Some(("ansible_host", "{{ 'node' ~ inventory_hostname[3:] ~ '.dc1.example.com' }}")))]
+    )]
+    #[case::numbered_host_with_suffix(
+        &[("web1", Some(("ansible_host", "web1.example.com"))), ("web2", Some(("ansible_host", "web2.example.com")))],
+        &[("web[1:2]", Some(("ansible_host", "{{ inventory_hostname ~ '.example.com' }}")))]
+    )]
+    #[case::host_named_alike(
+        &[("web1.example.com", Some(("ansible_host", "web1.example.com"))), ("web2.example.com", Some(("ansible_host", "web2.example.com")))],
+        &[("web[1:2].example.com", Some(("ansible_host", "{{ inventory_hostname }}")))]
+    )]
+    #[case::unnumbered_host(
+        &[("web1", Some(("ansible_host", "10.0.0.7"))), ("web2", Some(("ansible_host", "10.0.0.8")))],
+        &[("web1", Some(("ansible_host", "10.0.0.7"))), ("web2", Some(("ansible_host", "10.0.0.8")))]
+    )]
+    fn compact_hosts(
+        #[case] hosts: &[(&str, Option<(&str, &str)>)],
+        #[case] expected: &[(&str, Option<(&str, &str)>)],
+    ) {
+        // When:
+        let compacted = compact(sample_hosts(hosts));
+        // Then:
+        assert_eq!(compacted, sample_hosts(expected));
+        assert_eq!(expand(&compacted), sample_hosts(hosts));
+    }
+
+    proptest! {
+        #[test]
+        fn expanding_compacted_hosts_yields_the_same_hosts(
+            hosts in prop::collection::btree_map(
+                (0u64..25, prop::sample::select(vec![0usize, 2, 3])),
+                (prop::sample::select(vec!["22", "2222"]), any::<bool>()),
+                0..20,
+            ),
+        ) {
+            // Given:
+            let hosts = hosts
+                .into_iter()
+                .map(|((number, width), (port, is_numbered))| {
+                    let name = format!("web{number:0width$}.example.com");
+                    let mut vars = BTreeMap::from([
+                        ("ansible_port".to_string(), ValueType::String(port.to_string())),
+                    ]);
+                    if is_numbered {
+                        vars.insert(
+                            "ansible_host".to_string(),
+                            ValueType::String(format!("10.0.0.{number:0width$}")),
+                        );
+                    }
+                    (name, vars)
+                })
+                .collect::<Hosts>();
+
+            // When:
+            let compacted = compact(hosts.clone());
+
+            // Then:
+            prop_assert!(compacted.len() <= hosts.len());
+            prop_assert_eq!(expand(&compacted), hosts);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use crate::common::error::AppError;
+use crate::core::grouping::Key;
+use crate::core::patterns::glob;
+use crate::core::ssh_config::{Field, SshConfig};
+use crate::core::variables::ValueType;
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Conditions of a rule, as written in the rules file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConditions {
+    alias: Option<String>,
+    fields: IndexMap<String, String>,
+    matches: IndexMap<String, String>,
+    cidr: Option<String>,
+    has: Vec<String>,
+}
+
+/// Actions of a rule, as written in the rules file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawActions {
+    groups: Vec<String>,
+    vars: IndexMap<String, ValueType>,
+    remove: Vec<String>,
+    set: IndexMap<String, String>,
+}
+
+/// Rule, as written in the rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    when: RawConditions,
+    #[serde(default)]
+    then: RawActions,
+}
+
+/// Range of IP addresses, e.g. `10.0.0.0/8`.
+#[derive(Clone, Debug)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(cidr: &str) -> Result<Cidr, String> {
+        let (network, prefix) = cidr
+            .split_once('/')
+            .ok_or_else(|| format!("no `/` found in CIDR `{cidr}`"))?;
+        let network = network
+            .parse::<IpAddr>()
+            .map_err(|e| format!("invalid address in CIDR `{cidr}`: {e}"))?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        match prefix.parse::<u32>() {
+            Ok(prefix) if prefix <= bits => Ok(Cidr { network, prefix }),
+            _ => Err(format!(
+                "invalid prefix length in CIDR `{cidr}`, expected at most {bits}"
+            )),
+        }
+    }
+}
+
+impl Cidr {
+    fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Condition a host must meet for a rule to fire.
+#[derive(Clone, Debug)]
+enum Condition {
+    /// The alias matches the glob pattern.
+    Alias(Regex),
+    /// The value is exactly the provided one.
+    Equals(Key, String),
+    /// The value matches the regular expression.
+    Matches(Key, Regex),
+    /// The `HostName`, or alias, is an IP address within the range.
+    Cidr(Cidr),
+    /// The SSH option is set.
+    Has(Field),
+}
+
+impl Condition {
+    fn holds(&self, ssh_config: &SshConfig) -> bool {
+        match self {
+            Condition::Alias(pattern) => pattern.is_match(&ssh_config.host),
+            Condition::Equals(key, expected) => key.value(ssh_config) == Some(expected.as_str()),
+            Condition::Matches(key, regex) => key
+                .value(ssh_config)
+                .is_some_and(|value| regex.is_match(value)),
+            Condition::Cidr(cidr) => ssh_config
+                .host_name()
+                .parse::<IpAddr>()
+                .is_ok_and(|address| cidr.contains(address)),
+            Condition::Has(field) => ssh_config.fields.contains_key(field),
+        }
+    }
+}
+
+/// Rule adds the hosts meeting all its conditions to groups, sets their vars, and removes or
+/// overrides their SSH options.
+#[derive(Clone, Debug)]
+struct Rule {
+    name: String,
+    conditions: Vec<Condition>,
+    groups: Vec<String>,
+    vars: IndexMap<String, ValueType>,
+    remove: Vec<Field>,
+    set: IndexMap<Field, String>,
+}
+
+impl Rule {
+    fn new(index: usize, raw: RawRule) -> Result<Rule, AppError> {
+        let name = raw.name.unwrap_or_else(|| format!("#{}", index + 1));
+        let invalid = |reason: String| AppError::InvalidRules(format!("rule {name}: {reason}"));
+        let mut conditions = Vec::new();
+        if let Some(alias) = raw.when.alias {
+            conditions.push(Condition::Alias(glob(&alias)));
+        }
+        for (key, value) in raw.when.fields {
+            conditions.push(Condition::Equals(
+                Key::Field(field(&key).map_err(invalid)?),
+                value,
+            ));
+        }
+        for (key, regex) in raw.when.matches {
+            let regex = Regex::new(&regex).map_err(|e| invalid(e.to_string()))?;
+            conditions.push(Condition::Matches(
+                Key::from_str(&key).map_err(invalid)?,
+                regex,
+            ));
+        }
+        if let Some(cidr) = raw.when.cidr {
+            conditions.push(Condition::Cidr(Cidr::from_str(&cidr).map_err(invalid)?));
+        }
+        for key in raw.when.has {
+            conditions.push(Condition::Has(field(&key).map_err(invalid)?));
+        }
+        let remove = raw
+            .then
+            .remove
+            .iter()
+            .map(|key| field(key))
+            .collect::<Result<Vec<Field>, String>>()
+            .map_err(invalid)?;
+        let set = raw
+            .then
+            .set
+            .into_iter()
+            .map(|(key, value)| Ok((field(&key)?, value)))
+            .collect::<Result<IndexMap<Field, String>, String>>()
+            .map_err(invalid)?;
+        if let Some(field) = remove.iter().chain(set.keys()).find(|field| {
+            // Host and Match would start another block of the SSH configuration:
+            matches!(field, Field::Host | Field::Match)
+        }) {
+            return Err(invalid(format!("{field} cannot be removed or set")));
+        }
+        Ok(Rule {
+            name,
+            conditions,
+            groups: raw.then.groups,
+            vars: raw.then.vars,
+            remove,
+            set,
+        })
+    }
+
+    fn apply(&self, ssh_config: &mut SshConfig) {
+        let annotations = &mut ssh_config.annotations;
+        for group in &self.groups {
+            if !annotations.groups.contains(group) {
+                annotations.groups.push(group.to_owned());
+            }
+        }
+        for (key, value) in &self.vars {
+            annotations.vars.insert(key.to_owned(), value.clone());
+        }
+        for field in &self.remove {
+            ssh_config.fields.shift_remove(field);
+        }
+        for (field, value) in &self.set {
+            ssh_config.fields.insert(*field, value.to_owned());
+        }
+    }
+}
+
+/// Rules are evaluated against each host of the SSH configuration, in order, before it is
+/// translated into an Ansible inventory.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Load the rules of the provided YAML rules file.
+    pub fn load(reader: impl Read) -> Result<Rules, AppError> {
+        let rules: Vec<RawRule> =
+            serde_yaml::from_reader(reader).map_err(|e| AppError::InvalidRules(e.to_string()))?;
+        Ok(Rules {
+            rules: rules
+                .into_iter()
+                .enumerate()
+                .map(|(index, rule)| Rule::new(index, rule))
+                .collect::<Result<Vec<Rule>, AppError>>()?,
+        })
+    }
+
+    /// Apply the rules to each of the provided hosts, each rule seeing the host as changed by the
+    /// previous ones, and explain which rules fired for each host to the provided writer, if any.
+    pub fn apply(
+        &self,
+        ssh_configs: &mut [SshConfig],
+        mut explanation: Option<&mut dyn Write>,
+    ) -> Result<(), io::Error> {
+        for ssh_config in ssh_configs {
+            let mut fired = Vec::new();
+            for rule in &self.rules {
+                if rule
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.holds(ssh_config))
+                {
+                    rule.apply(ssh_config);
+                    fired.push(rule.name.as_str());
+                }
+            }
+            if let Some(writer) = explanation.as_deref_mut() {
+                if fired.is_empty() {
+                    writeln!(writer, "{}: no rule fired", ssh_config.host)?;
+                } else {
+                    writeln!(writer, "{}: {}", ssh_config.host, fired.join(", "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn field(key: &str) -> Result<Field, String> {
+    Field::from_key(key).ok_or_else(|| format!("unknown SSH option: {key}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rules;
+    use crate::common::error::AppError;
+    use crate::core::ssh_config::{Annotations, Field, SshConfig};
+    use crate::core::variables::ValueType;
+    use indexmap::IndexMap;
+    use rstest::rstest;
+
+    fn sample_ssh_configs() -> Vec<SshConfig> {
+        Vec::from([
+            SshConfig {
+                host: "web-eu-1".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "10.1.0.1".to_string()),
+                    (Field::User, "deploy".to_string()),
+                    (Field::LogLevel, "FATAL".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+            SshConfig {
+                host: "db-us-1".to_string(),
+                fields: IndexMap::from([
+                    (Field::HostName, "db1.us.example.com".to_string()),
+                    (Field::ProxyJump, "bastion".to_string()),
+                ]),
+                annotations: Annotations::default(),
+            },
+        ])
+    }
+
+    #[test]
+    fn apply_rules() -> Result<(), AppError> {
+        // Given:
+        let rules = Rules::load(
+            r#"
+- name: private network
+  when:
+    cidr: 10.0.0.0/8
+  then:
+    groups: [private]
+    remove: [LogLevel]
+- name: EU web servers
+  when:
+    alias: web-eu-*
+    fields:
+      user: deploy
+    matches:
+      hostname: '^10\.1\.'
+  then:
+    groups: [web, eu]
+    vars:
+      http_port: 8080
+    set:
+      User: www
+- when:
+    has: [ProxyJump]
+  then:
+    vars:
+      behind_bastion: true
+"#
+            .as_bytes(),
+        )?;
+        let mut ssh_configs = sample_ssh_configs();
+        let mut explanation = Vec::new();
+
+        // When:
+        rules.apply(&mut ssh_configs, Some(&mut explanation))?;
+
+        // Then:
+        assert_eq!(
+            ssh_configs[0].fields,
+            IndexMap::from([
+                (Field::HostName, "10.1.0.1".to_string()),
+                (Field::User, "www".to_string()),
+            ])
+        );
+        assert_eq!(
+            ssh_configs[0].annotations.groups,
+            Vec::from(["private".to_string(), "web".to_string(), "eu".to_string()])
+        );
+        assert_eq!(
+            ssh_configs[0].annotations.vars,
+            IndexMap::from([("http_port".to_string(), ValueType::Int64(8080))])
+        );
+        assert_eq!(
+            ssh_configs[1].annotations.vars,
+            IndexMap::from([("behind_bastion".to_string(), ValueType::Bool(true))])
+        );
+        assert_eq!(
+            String::from_utf8(explanation)?,
+            "web-eu-1: private network, EU web servers\ndb-us-1: #3\n"
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::unknown_option(
+        "- when:\n    has: [Foo]\n",
+        "Invalid rules file: rule #1: unknown SSH option: Foo"
+    )]
+    #[case::invalid_regex(
+        "- name: web\n  when:\n    matches:\n      alias: '['\n",
+        "Invalid rules file: rule web: regex parse error:\n    [\n    ^\nerror: unclosed character class"
+    )]
+    #[case::invalid_cidr(
+        "- when:\n    cidr: 10.0.0.0/33\n",
+        "Invalid rules file: rule #1: invalid prefix length in CIDR `10.0.0.0/33`, expected at most 32"
+    )]
+    #[case::set_host(
+        "- then:\n    set:\n      Host: foo\n",
+        "Invalid rules file: rule #1: Host cannot be removed or set"
+    )]
+    fn load_invalid_rules(#[case] input: &str, #[case] expected: &str) {
+        // When:
+        let result = Rules::load(input.as_bytes());
+        // Then:
+        assert_eq!(result.err().unwrap().to_string(), expected);
+    }
+}
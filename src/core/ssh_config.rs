@@ -1,6 +1,13 @@
-use std::collections::{BTreeMap, HashMap};
+use crate::common::error::AppError;
+use crate::core::naming;
+use crate::core::options::Order;
+use crate::core::variables::ValueType;
+use indexmap::IndexMap;
+use regex::Regex;
+use std::collections::HashMap;
 use std::hash::Hash;
-use std::io::BufRead;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter};
 use tracing::warn;
@@ -124,51 +131,265 @@ lazy_static! {
         .collect::<HashMap<String, Field>>();
 }
 
+impl Field {
+    /// Look up the field named after the provided SSH config key, which is case-insensitive.
+    pub fn from_key(key: &str) -> Option<Field> {
+        FIELDS.get(&key.to_lowercase()).copied()
+    }
+}
+
+/// Prefix of the comments annotating a host, e.g. `# s2a: groups=web,canary`.
+const ANNOTATION_PREFIX: &str = "s2a:";
+
+/// Names of the sections written by known generators of SSH configurations, e.g. by
+/// `gcloud compute config-ssh`, wrapping their hosts in `# <name> Section` and
+/// `# End of <name> Section` comments. Other comments ending in `Section` are free text.
+const GENERATED_SECTIONS: &[&str] = &["Google Compute Engine"];
+
+lazy_static! {
+    /// Comment starting a section of hosts naming its environment.
+    static ref SECTION_START: Regex =
+        Regex::new(r"^s2a-environment:\s*(?<environment>\S.*)$").unwrap();
+    /// Comment ending a section of hosts naming its environment.
+    static ref SECTION_END: Regex = Regex::new(r"^s2a-environment-end:\s*\S.*$").unwrap();
+}
+
+/// Whether the provided comment, stripped of its `#`, ends a section.
+fn is_section_end(comment: &str) -> bool {
+    SECTION_END.is_match(comment)
+        || GENERATED_SECTIONS
+            .iter()
+            .any(|name| comment == format!("End of {name} Section"))
+}
+
+/// Section of the SSH configuration, whose hosts belong to their own environment, e.g.
+/// `# Google Compute Engine Section` ... `# End of Google Compute Engine Section`, for
+/// `google_compute_engine`, or `# s2a-environment: staging` ... `# s2a-environment-end: staging`.
+struct Section {
+    start: String,
+    end: String,
+    environment: String,
+    line: usize,
+}
+
+impl Section {
+    /// Parse the provided comment, stripped of its `#`, at the provided line, if it starts a
+    /// section.
+    fn start(comment: &str, line: usize) -> Option<Section> {
+        let (end, environment) = match SECTION_START.captures(comment) {
+            Some(captures) => (
+                format!("s2a-environment-end: {}", &captures["environment"]),
+                captures["environment"].to_owned(),
+            ),
+            None => {
+                let name = GENERATED_SECTIONS
+                    .iter()
+                    .find(|name| comment == format!("{name} Section"))?;
+                (
+                    format!("End of {name} Section"),
+                    naming::sanitise(&name.to_lowercase()),
+                )
+            }
+        };
+        Some(Section {
+            start: comment.to_owned(),
+            end,
+            environment,
+            line,
+        })
+    }
+
+    /// Fail on the provided comment, at the provided line, marking a section within this one.
+    fn unbalanced(&self, comment: &str, line: usize) -> AppError {
+        AppError::UnbalancedSection {
+            line,
+            reason: format!(
+                "`{comment}` within section `{}` started at line {}, expected `{}` first",
+                self.start, self.line, self.end
+            ),
+        }
+    }
+}
+
+/// Annotations hold the inventory metadata kept next to a host's connection details, in comments
+/// of its `Host` block, e.g. `# s2a: groups=web,canary` or `# s2a: var http_port=8080`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotations {
+    pub groups: Vec<String>,
+    pub vars: IndexMap<String, ValueType>,
+    /// The free-text comment right above the `Host` line, if any.
+    pub description: Option<String>,
+    /// The environment of the section the `Host` line is in, if any.
+    pub environment: Option<String>,
+}
+
+impl Annotations {
+    /// Parse the provided annotation, i.e. a comment stripped of its `s2a:` prefix, of the
+    /// provided host, warning about and skipping it if it is not well-formed.
+    fn parse(&mut self, host: &str, annotation: &str) {
+        if let Some(groups) = annotation.strip_prefix("groups=") {
+            self.groups.extend(
+                groups
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|group| !group.is_empty())
+                    .map(str::to_owned),
+            );
+        } else if let Some((key, value)) = annotation
+            .strip_prefix("var ")
+            .and_then(|var| var.split_once('='))
+            .filter(|(key, _)| !key.trim().is_empty())
+        {
+            let (key, value) = (key.trim(), value.trim());
+            // Typed like --var, which never fails, defaulting to a string:
+            let value =
+                ValueType::from_str(value).unwrap_or_else(|_| ValueType::String(value.to_owned()));
+            if let Some(old_value) = self.vars.insert(key.to_owned(), value) {
+                warn!(host, key, %old_value, "Overwrote previous value of annotated variable");
+            }
+        } else {
+            warn!(
+                host,
+                annotation, "Invalid annotation: expected groups=GROUP[,GROUP...] or var KEY=VALUE"
+            );
+        }
+    }
+
+    /// Merge the provided annotations, of a duplicate of this host, into these ones, which take
+    /// precedence. The environment is left as-is.
+    pub fn merge(&mut self, other: &Annotations) {
+        for group in &other.groups {
+            if !self.groups.contains(group) {
+                self.groups.push(group.to_owned());
+            }
+        }
+        for (key, value) in &other.vars {
+            self.vars
+                .entry(key.to_owned())
+                .or_insert_with(|| value.clone());
+        }
+        if self.description.is_none() {
+            self.description.clone_from(&other.description);
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct SshConfig {
     pub host: String,
-    pub fields: BTreeMap<Field, String>,
+    pub fields: IndexMap<Field, String>,
+    pub annotations: Annotations,
 }
 
 impl SshConfig {
     pub fn new() -> SshConfig {
         SshConfig {
             host: "*".to_string(),
-            fields: BTreeMap::new(),
+            fields: IndexMap::new(),
+            annotations: Annotations::default(),
         }
     }
 
+    /// The host ssh connects to: the alias, unless HostName is set.
+    pub fn host_name(&self) -> &str {
+        self.fields.get(&Field::HostName).unwrap_or(&self.host)
+    }
+
+    /// Whether this SSH config has neither fields, nor groups or vars annotated.
     pub fn is_empty(&self) -> bool {
         self.fields.is_empty()
+            && self.annotations.groups.is_empty()
+            && self.annotations.vars.is_empty()
     }
 }
 
 impl SshConfig {
-    pub fn parse(reader: &mut impl BufRead) -> Result<Vec<SshConfig>, std::io::Error> {
+    pub fn parse(reader: &mut impl BufRead) -> Result<Vec<SshConfig>, AppError> {
+        SshConfig::parse_lines(reader, false)
+    }
+
+    /// Parse the provided SSH config, failing on `Match` blocks and on options repeated within a
+    /// `Host` block, which a normalised SSH config, e.g. the sidecar one, cannot keep as-is.
+    pub fn parse_strict(reader: &mut impl BufRead) -> Result<Vec<SshConfig>, AppError> {
+        SshConfig::parse_lines(reader, true)
+    }
+
+    fn parse_lines(reader: &mut impl BufRead, strict: bool) -> Result<Vec<SshConfig>, AppError> {
         let mut ssh_configs = Vec::new(); // There can me more than one SSH config in a SSH config file.
         let mut ssh_config = SshConfig::new();
+        // Free-text comments right above the next line, describing the host if it is a Host line:
+        let mut comments = Vec::new();
+        let mut section: Option<Section> = None;
 
         let mut line = String::new(); // Reuse the same memory for each line to reduce allocations.
+        let mut number = 0;
         loop {
             line.clear();
             if reader.read_line(&mut line)? == 0 {
+                if let Some(section) = section {
+                    return Err(AppError::UnbalancedSection {
+                        line: section.line,
+                        reason: format!(
+                            "`{}` never ends, expected `{}`",
+                            section.start, section.end
+                        ),
+                    });
+                }
                 if !ssh_config.is_empty() {
                     ssh_configs.push(ssh_config);
                 }
                 break;
             }
+            number += 1;
             let line = line.trim(); // Remove leading and trailing whitespaces.
             if line.is_empty() {
+                comments.clear();
                 continue; // Skip empty lines.
             }
-            if line.starts_with('#') {
-                continue; // Skip comments.
+            if let Some(comment) = line.strip_prefix('#') {
+                let comment = comment.trim();
+                if is_section_end(comment) {
+                    match section.take() {
+                        Some(started) if started.end == comment => {}
+                        Some(started) => return Err(started.unbalanced(comment, number)),
+                        None => {
+                            return Err(AppError::UnbalancedSection {
+                                line: number,
+                                reason: format!("`{comment}` outside of any section"),
+                            })
+                        }
+                    }
+                    comments.clear();
+                } else if let Some(started) = Section::start(comment, number) {
+                    if let Some(section) = &section {
+                        return Err(section.unbalanced(comment, number));
+                    }
+                    section = Some(started);
+                    comments.clear();
+                } else if let Some(annotation) = comment.strip_prefix(ANNOTATION_PREFIX) {
+                    ssh_config
+                        .annotations
+                        .parse(&ssh_config.host, annotation.trim());
+                    comments.clear();
+                } else if !comment.is_empty() {
+                    comments.push(comment.to_owned());
+                }
+                continue; // Skip other comments.
             }
+            let description = (!comments.is_empty()).then(|| comments.join(" "));
+            comments.clear();
             if let Some((key, value)) = line.split_once(' ') {
-                let case_insensitive_key = key.to_lowercase(); // SSH config keys are case-insensitive.
                 let value = value.trim();
-                if let Some(&field) = FIELDS.get(&case_insensitive_key) {
+                if let Some(field) = Field::from_key(key) {
                     // Known SSH config field:
+                    if strict && field == Field::Match {
+                        return Err(AppError::InvalidSshConfig {
+                            host: ssh_config.host,
+                            reason: format!(
+                                "`{line}` at line {number}, Match blocks cannot be normalised"
+                            ),
+                        });
+                    }
                     if field == Field::Host {
                         if !ssh_config.is_empty() {
                             // Assume the beginning of a new SSH config, and add the SSH config being processed so far to our list of SSH configs:
@@ -177,9 +398,21 @@ impl SshConfig {
                             ssh_config = SshConfig::new();
                         }
                         ssh_config.host = value.to_owned();
+                        ssh_config.annotations.description = description;
+                        ssh_config.annotations.environment = section
+                            .as_ref()
+                            .map(|section| section.environment.to_owned());
                     } else if let Some(old_value) =
                         ssh_config.fields.insert(field, value.to_owned())
                     {
+                        if strict {
+                            return Err(AppError::InvalidSshConfig {
+                                host: ssh_config.host,
+                                reason: format!(
+                                    "{field} repeated at line {number}, repeated options cannot be normalised"
+                                ),
+                            });
+                        }
                         warn!(
                             key,
                             old_value,
@@ -198,14 +431,40 @@ impl SshConfig {
     }
 }
 
+impl SshConfig {
+    /// Write the provided SSH configurations in the SSH config format, normalised, i.e. with
+    /// canonical keys, in the provided order.
+    pub fn write(
+        ssh_configs: &[SshConfig],
+        order: Order,
+        writer: &mut impl Write,
+    ) -> Result<(), io::Error> {
+        for ssh_config in ssh_configs {
+            writeln!(writer, "Host {}", ssh_config.host)?;
+            let mut fields = ssh_config.fields.iter().collect::<Vec<(&Field, &String)>>();
+            if order == Order::Sorted {
+                fields.sort();
+            }
+            for (field, value) in fields {
+                writeln!(writer, "  {field} {value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Field, SshConfig};
+    use super::{Annotations, Field, SshConfig};
+    use crate::common::error::AppError;
     use crate::common::testing::utilities::SAMPLE_SSH_CONFIG;
-    use std::collections::BTreeMap;
+    use crate::core::options::Order;
+    use crate::core::variables::ValueType;
+    use indexmap::IndexMap;
+    use rstest::rstest;
 
     #[test]
-    fn parse_ssh_config() -> Result<(), std::io::Error> {
+    fn parse_ssh_config() -> Result<(), AppError> {
         // Given:
         let mut input = SAMPLE_SSH_CONFIG.as_bytes();
 
@@ -218,7 +477,7 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([
+                fields: IndexMap::from([
                     (Field::HostName, "127.0.0.1".to_string()),
                     (Field::User, "vagrant".to_string()),
                     (Field::Port, "50022".to_string()),
@@ -231,13 +490,14 @@ mod tests {
                     (Field::PubkeyAcceptedKeyTypes, "+ssh-rsa".to_string()),
                     (Field::HostKeyAlgorithms, "+ssh-rsa".to_string()),
                 ]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_empty_string() -> Result<(), std::io::Error> {
+    fn parse_empty_string() -> Result<(), AppError> {
         // Given:
         let mut input = "".as_bytes();
 
@@ -250,7 +510,7 @@ mod tests {
     }
 
     #[test]
-    fn parse_ssh_config_with_empty_lines() -> Result<(), std::io::Error> {
+    fn parse_ssh_config_with_empty_lines() -> Result<(), AppError> {
         // Given:
         let mut input = r#"Host default
 
@@ -268,14 +528,15 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_ssh_config_with_comments() -> Result<(), std::io::Error> {
+    fn parse_ssh_config_with_comments() -> Result<(), AppError> {
         // Given:
         let mut input = r#"Host default
   # The following line is the hostname:
@@ -292,14 +553,132 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_ssh_config_with_lowercased_keys() -> Result<(), std::io::Error> {
+    fn parse_ssh_config_with_annotations() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"# Web server,
+# behind the load balancer.
+Host web1
+  # s2a: groups=web, canary
+  # s2a: var http_port=8080
+  # s2a: unknown
+  HostName 192.168.0.1
+# Not a description, as separated from the Host line by an empty line.
+
+Host web2
+  HostName 192.168.0.2
+"#
+        .as_bytes();
+
+        // When:
+        let ssh_configs = SshConfig::parse(&mut input)?;
+
+        // Then:
+        assert_eq!(2, ssh_configs.len());
+        assert_eq!(
+            ssh_configs[0].annotations,
+            Annotations {
+                groups: Vec::from(["web".to_string(), "canary".to_string()]),
+                vars: IndexMap::from([("http_port".to_string(), ValueType::Int64(8080))]),
+                description: Some("Web server, behind the load balancer.".to_string()),
+                environment: None,
+            }
+        );
+        assert_eq!(ssh_configs[1].annotations, Annotations::default());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ssh_config_with_sections() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host bastion
+  HostName 192.168.0.1
+# Google Compute Engine Section
+# Generated by gcloud.
+Host web1.europe-west1-b.my-project
+  HostName 34.76.0.1
+# End of Google Compute Engine Section
+# s2a-environment: staging
+Host web2
+  HostName 192.168.0.2
+# s2a-environment-end: staging
+"#
+        .as_bytes();
+
+        // When:
+        let ssh_configs = SshConfig::parse(&mut input)?;
+
+        // Then:
+        assert_eq!(
+            ssh_configs
+                .iter()
+                .map(|ssh_config| ssh_config.annotations.environment.as_deref())
+                .collect::<Vec<Option<&str>>>(),
+            Vec::from([None, Some("google_compute_engine"), Some("staging")])
+        );
+        assert_eq!(
+            ssh_configs[1].annotations.description.as_deref(),
+            Some("Generated by gcloud.")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_ssh_config_with_free_text_comment_ending_in_section() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"# Database Section
+Host db
+  HostName 192.168.0.1
+"#
+        .as_bytes();
+
+        // When:
+        let ssh_configs = SshConfig::parse(&mut input)?;
+
+        // Then:
+        assert_eq!(1, ssh_configs.len());
+        assert_eq!(ssh_configs[0].annotations.environment, None);
+        assert_eq!(
+            ssh_configs[0].annotations.description.as_deref(),
+            Some("Database Section")
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::never_ends(
+        "# s2a-environment: staging\nHost web1\n",
+        "Unbalanced section marker at line 1 of SSH config. Reason: `s2a-environment: staging` never ends, expected `s2a-environment-end: staging`"
+    )]
+    #[case::outside_of_any_section(
+        "Host web1\n# End of Google Compute Engine Section\n",
+        "Unbalanced section marker at line 2 of SSH config. Reason: `End of Google Compute Engine Section` outside of any section"
+    )]
+    #[case::mismatched_end(
+        "# s2a-environment: staging\nHost web1\n# s2a-environment-end: prod\n",
+        "Unbalanced section marker at line 3 of SSH config. Reason: `s2a-environment-end: prod` within section `s2a-environment: staging` started at line 1, expected `s2a-environment-end: staging` first"
+    )]
+    #[case::nested(
+        "# Google Compute Engine Section\n# s2a-environment: staging\n",
+        "Unbalanced section marker at line 2 of SSH config. Reason: `s2a-environment: staging` within section `Google Compute Engine Section` started at line 1, expected `End of Google Compute Engine Section` first"
+    )]
+    fn parse_ssh_config_with_unbalanced_sections(#[case] input: &str, #[case] expected: &str) {
+        // When:
+        let result = SshConfig::parse(&mut input.as_bytes());
+        // Then:
+        assert_eq!(result.err().unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn parse_ssh_config_with_lowercased_keys() -> Result<(), AppError> {
         // Given:
         let mut input = "host default\nhostname 127.0.0.1".as_bytes();
 
@@ -312,14 +691,15 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_ssh_config_with_duplicate_field_keeps_last_value() -> Result<(), std::io::Error> {
+    fn parse_ssh_config_with_duplicate_field_keeps_last_value() -> Result<(), AppError> {
         // Given:
         let mut input = r#"Host default
   HostName 127.0.0.1
@@ -335,14 +715,15 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "127.0.0.2".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "127.0.0.2".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_ssh_config_with_unknown_field() -> Result<(), std::io::Error> {
+    fn parse_ssh_config_with_unknown_field() -> Result<(), AppError> {
         // Given:
         let mut input = r#"Host default
   Unknown foobar
@@ -358,14 +739,15 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_ssh_config_with_non_well_formed_line() -> Result<(), std::io::Error> {
+    fn parse_ssh_config_with_non_well_formed_line() -> Result<(), AppError> {
         // Given:
         let mut input = r#"Host default
   invalid-line
@@ -381,14 +763,15 @@ mod tests {
             ssh_configs[0],
             SshConfig {
                 host: "default".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "127.0.0.1".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
 
     #[test]
-    fn parse_two_ssh_configs() -> Result<(), std::io::Error> {
+    fn parse_two_ssh_configs() -> Result<(), AppError> {
         // Given:
         let mut input = r#"Host host1
   HostName 192.168.0.1
@@ -406,16 +789,65 @@ Host host2
             ssh_configs[0],
             SshConfig {
                 host: "host1".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "192.168.0.1".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "192.168.0.1".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         assert_eq!(
             ssh_configs[1],
             SshConfig {
                 host: "host2".to_string(),
-                fields: BTreeMap::from([(Field::HostName, "192.168.0.2".to_string()),]),
+                fields: IndexMap::from([(Field::HostName, "192.168.0.2".to_string()),]),
+                annotations: Annotations::default(),
             }
         );
         Ok(())
     }
+
+    #[test]
+    fn write_ssh_configs() -> Result<(), AppError> {
+        // Given:
+        let mut input = r#"Host host1
+  hostname 192.168.0.1
+  ProxyJump host2
+Host host2
+  HostName 192.168.0.2
+"#
+        .as_bytes();
+        let ssh_configs = SshConfig::parse(&mut input)?;
+        let mut output = Vec::new();
+
+        // When:
+        SshConfig::write(&ssh_configs, Order::Sorted, &mut output)?;
+
+        // Then:
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            r#"Host host1
+  HostName 192.168.0.1
+  ProxyJump host2
+Host host2
+  HostName 192.168.0.2
+"#
+        );
+        assert_eq!(SshConfig::parse(&mut output.as_slice())?.len(), 2);
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::match_block(
+        "Host web1\n  HostName 10.0.0.1\nMatch exec \"true\"\n  User deploy\n",
+        "Invalid SSH config for host \"web1\". Reason: `Match exec \"true\"` at line 3, Match blocks cannot be normalised"
+    )]
+    #[case::repeated_option(
+        "Host web1\n  IdentityFile ~/.ssh/a\n  IdentityFile ~/.ssh/b\n",
+        "Invalid SSH config for host \"web1\". Reason: IdentityFile repeated at line 3, repeated options cannot be normalised"
+    )]
+    fn parse_strict_invalid_ssh_config(#[case] input: &str, #[case] expected: &str) {
+        // When:
+        let result = SshConfig::parse_strict(&mut input.as_bytes());
+        // Then:
+        assert_eq!(result.err().unwrap().to_string(), expected);
+    }
 }
@@ -1,6 +1,9 @@
+use crate::core::patterns::glob;
 use derive_more::Display;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use thiserror::Error;
 
@@ -10,6 +13,11 @@ lazy_static! {
 
 #[derive(Clone, Debug, Deserialize, Display, PartialEq, Serialize)]
 pub enum ValueType {
+    /// A string Ansible must not template, serialised with Ansible's `!unsafe` YAML tag. Never
+    /// deserialised, for a map with a single `unsafe` key to remain a map.
+    #[display("{_0}")]
+    #[serde(rename = "unsafe", skip_deserializing)]
+    Unsafe(String),
     #[display("{_0}")]
     #[serde(untagged)]
     Bool(bool),
@@ -22,6 +30,47 @@ pub enum ValueType {
     #[display("{_0}")]
     #[serde(untagged)]
     String(String),
+    #[display("{_0:?}")]
+    #[serde(untagged)]
+    List(Vec<ValueType>),
+    #[display("{_0:?}")]
+    #[serde(untagged)]
+    Map(BTreeMap<String, ValueType>),
+}
+
+/// Jinja expression of the inventory's directory, which paths rewritten relative to the inventory
+/// start with.
+pub const INVENTORY_DIR: &str = "{{ inventory_dir }}";
+
+/// Jinja expression of the inventory's directory, shell-quoted, e.g. within ssh arguments.
+pub const QUOTED_INVENTORY_DIR: &str = "{{ inventory_dir | quote }}";
+
+impl ValueType {
+    /// Convert a string read from the SSH configuration, marking it as unsafe if it contains Jinja
+    /// delimiters, so that Ansible neither evaluates it, nor fails templating it at runtime.
+    pub fn from_ssh_config(value: String, allow_templating: bool) -> ValueType {
+        if !allow_templating && has_jinja_delimiters(&value) {
+            ValueType::Unsafe(value)
+        } else {
+            ValueType::String(value)
+        }
+    }
+}
+
+/// Escape the provided string's Jinja delimiters, if any, so that Ansible templates it back into
+/// itself, e.g. as part of a value which Ansible must otherwise template.
+pub fn escape_jinja(value: &str) -> Cow<'_, str> {
+    if has_jinja_delimiters(value) {
+        Cow::Owned(format!("{{% raw %}}{value}{{% endraw %}}"))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn has_jinja_delimiters(value: &str) -> bool {
+    ["{{", "{%", "{#"]
+        .iter()
+        .any(|delimiter| value.contains(delimiter))
 }
 
 #[derive(Debug, Display, Error, PartialEq, Eq)]
@@ -50,11 +99,50 @@ impl FromStr for ValueType {
     }
 }
 
+/// HostVar sets a variable on the hosts whose alias matches a glob pattern, parsed from
+/// `PATTERN:NAME:VALUE`, e.g. `web*:weight:10`, the value being typed like `--var`'s.
+#[derive(Clone, Debug)]
+pub struct HostVar {
+    pub pattern: String,
+    regex: Regex,
+    pub name: String,
+    pub value: ValueType,
+}
+
+impl FromStr for HostVar {
+    type Err = String;
+
+    fn from_str(host_var: &str) -> Result<HostVar, String> {
+        let mut parts = host_var.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(pattern), Some(name), Some(value)) if !pattern.is_empty() && !name.is_empty() => {
+                Ok(HostVar {
+                    pattern: pattern.to_owned(),
+                    regex: glob(pattern),
+                    name: name.to_owned(),
+                    value: ValueType::from_str(value).map_err(|e| e.to_string())?,
+                })
+            }
+            _ => Err(format!(
+                "invalid host var: expected PATTERN:NAME:VALUE in `{host_var}`"
+            )),
+        }
+    }
+}
+
+impl HostVar {
+    /// Whether the provided alias matches this host var's pattern.
+    pub fn matches(&self, alias: &str) -> bool {
+        self.regex.is_match(alias)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::core::variables::ValueType;
+    use crate::core::variables::{escape_jinja, HostVar, ValueType};
     use rstest::rstest;
     use serde_yaml;
+    use std::collections::BTreeMap;
     use std::str::FromStr;
 
     use super::ParseValueTypeError;
@@ -89,6 +177,9 @@ mod tests {
     #[case::serialize_float(ValueType::Float64(1.23), "1.23")]
     #[case::serialize_str(ValueType::String("foo".to_string()), "foo")]
     #[case::serialize_int_as_str(ValueType::String("22".to_string()), "'22'")]
+    #[case::serialize_list(ValueType::List(Vec::from([ValueType::String("db1-admin".to_string())])), "- db1-admin")]
+    #[case::serialize_map(ValueType::Map(BTreeMap::from([("IdentitiesOnly".to_string(), ValueType::String("yes".to_string()))])), "IdentitiesOnly: yes")]
+    #[case::serialize_unsafe(ValueType::Unsafe("{{ foo }}".to_string()), "!unsafe '{{ foo }}'")]
     fn serialize_to_yaml(
         #[case] input: ValueType,
         #[case] expected: &str,
@@ -99,4 +190,82 @@ mod tests {
         assert_eq!(yaml.trim(), expected);
         Ok(())
     }
+
+    #[rstest]
+    // Given:
+    #[case::deserialize_str("foo", ValueType::String("foo".to_string()))]
+    #[case::deserialize_unsafe_key("unsafe: foo", ValueType::Map(BTreeMap::from([("unsafe".to_string(), ValueType::String("foo".to_string()))])))]
+    fn deserialize_from_yaml(
+        #[case] input: &str,
+        #[case] expected: ValueType,
+    ) -> Result<(), serde_yaml::Error> {
+        // When:
+        let value = serde_yaml::from_str::<ValueType>(input)?;
+        // Then:
+        assert_eq!(value, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    // Given:
+    #[case::plain("ssh -W %h:%p bastion", false, ValueType::String("ssh -W %h:%p bastion".to_string()))]
+    #[case::expression("echo {{ foo }}", false, ValueType::Unsafe("echo {{ foo }}".to_string()))]
+    #[case::statement("{% if x %}", false, ValueType::Unsafe("{% if x %}".to_string()))]
+    #[case::comment("{# x #}", false, ValueType::Unsafe("{# x #}".to_string()))]
+    #[case::allow_templating("echo {{ foo }}", true, ValueType::String("echo {{ foo }}".to_string()))]
+    fn from_ssh_config(
+        #[case] input: &str,
+        #[case] allow_templating: bool,
+        #[case] expected: ValueType,
+    ) {
+        // When:
+        let value = ValueType::from_ssh_config(input.to_string(), allow_templating);
+        // Then:
+        assert_eq!(value, expected);
+    }
+
+    #[rstest]
+    // Given:
+    #[case::plain("/keys/id", "/keys/id")]
+    #[case::expression("'/{{ x }}'", "{% raw %}'/{{ x }}'{% endraw %}")]
+    fn escape_jinja_delimiters(#[case] input: &str, #[case] expected: &str) {
+        // When:
+        let escaped = escape_jinja(input);
+        // Then:
+        assert_eq!(escaped, expected);
+    }
+
+    #[rstest]
+    // Given:
+    #[case::single_host("db1:postgres_role:primary", "db1", "postgres_role", ValueType::String("primary".to_string()))]
+    #[case::glob("web*:weight:10", "web12", "weight", ValueType::Int64(10))]
+    #[case::colon_in_value("db1:url:http://db1:5432", "db1", "url", ValueType::String("http://db1:5432".to_string()))]
+    fn parse_host_var(
+        #[case] input: &str,
+        #[case] alias: &str,
+        #[case] name: &str,
+        #[case] value: ValueType,
+    ) {
+        // When:
+        let host_var = HostVar::from_str(input).unwrap();
+        // Then:
+        assert!(host_var.matches(alias));
+        assert_eq!(host_var.name, name);
+        assert_eq!(host_var.value, value);
+    }
+
+    #[rstest]
+    // Given:
+    #[case::missing_value("db1:postgres_role")]
+    #[case::empty_pattern(":postgres_role:primary")]
+    #[case::empty_name("db1::primary")]
+    fn parse_invalid_host_var(#[case] input: &str) {
+        // When:
+        let result = HostVar::from_str(input);
+        // Then:
+        assert_eq!(
+            result.err().unwrap(),
+            format!("invalid host var: expected PATTERN:NAME:VALUE in `{input}`")
+        );
+    }
 }
@@ -8,6 +8,7 @@ use crate::common::cli;
 use crate::common::error::AppError;
 use crate::core::parser::parse_and_serialise_as_yaml;
 use clap::Parser;
+use std::io::Write;
 use tracing::info;
 
 fn main() -> Result<(), AppError> {
@@ -17,7 +18,15 @@ fn main() -> Result<(), AppError> {
     args.validate()?;
     let mut input = args.input()?;
     let mut output = args.output()?;
-    parse_and_serialise_as_yaml(&args.environment, &args.vars, &mut input, &mut output)?;
+    let mut sidecar = args.sidecar()?;
+    parse_and_serialise_as_yaml(
+        &args.environment,
+        &args.vars,
+        &args.options()?,
+        &mut input,
+        &mut output,
+        sidecar.as_mut().map(|sidecar| sidecar as &mut dyn Write),
+    )?;
     info!("That's all folks! 👋🏻😊");
     Ok(())
 }
@@ -131,4 +140,173 @@ mod tests {
         dir.close()?; // clean-up.
         Ok(())
     }
+
+    #[test]
+    fn s2a_resolve_proxy_jump_as_proxy_command() {
+        // Given:
+        let mut cmd = Command::cargo_bin("s2a").unwrap();
+        let ssh_config = r#"Host bastion
+  HostName bastion.example.com
+  User jump
+Host app
+  HostName 10.0.0.1
+  ProxyJump bastion"#;
+
+        // When:
+        let assert = cmd
+            .arg("--proxy-jump")
+            .arg("proxy-command")
+            .write_stdin(ssh_config)
+            .assert();
+
+        // Then:
+        assert.success().code(eq(0)).stdout(eq(r#"local:
+  hosts:
+    app:
+      ansible_host: 10.0.0.1
+      ansible_ssh_common_args: -o 'ProxyCommand=ssh -W %h:%p -l jump bastion.example.com'
+    bastion:
+      ansible_host: bastion.example.com
+      ansible_user: jump
+"#));
+    }
+
+    #[test]
+    fn s2a_resolve_proxy_jump_to_unknown_host_fails() {
+        // Given:
+        let mut cmd = Command::cargo_bin("s2a").unwrap();
+        let ssh_config = "Host app\n  HostName 10.0.0.1\n  ProxyJump bastion";
+
+        // When:
+        let assert = cmd
+            .arg("--proxy-jump")
+            .arg("inline")
+            .write_stdin(ssh_config)
+            .assert();
+
+        // Then:
+        assert.failure().code(eq(1));
+    }
+
+    #[test]
+    fn s2a_read_stdin_write_stdout_with_mapping_file() -> Result<(), std::io::Error> {
+        // Given:
+        let (dir, mapping_filepath) = temp_file(
+            "test_mapping_file",
+            "LogLevel:\n  placement: drop\nIdentitiesOnly:\n  placement: host-var\n  variable: identities_only\n  transform: boolean\n",
+        )?;
+        let mut cmd = Command::cargo_bin("s2a").unwrap();
+
+        // When:
+        let assert = cmd
+            .arg("--mapping-filepath")
+            .arg(mapping_filepath)
+            .write_stdin(SAMPLE_SSH_CONFIG)
+            .assert();
+
+        // Then:
+        assert.success().code(eq(0)).stdout(eq(r#"local:
+  hosts:
+    default:
+      ansible_host: 127.0.0.1
+      ansible_port: 50022
+      ansible_user: vagrant
+      ansible_ssh_private_key_file: /path/to/private_key
+      ansible_host_key_checking: false
+      ansible_ssh_args: -C -o ControlMaster=auto -o ControlPersist=60s -o PasswordAuthentication=no
+      ansible_ssh_extra_args: -o HostKeyAlgorithms=+ssh-rsa -o PubkeyAcceptedKeyTypes=+ssh-rsa -o UserKnownHostsFile=/dev/null
+      identities_only: true
+"#));
+
+        dir.close()?; // clean-up.
+        Ok(())
+    }
+
+    #[test]
+    fn s2a_read_stdin_write_stdout_with_rules_file() -> Result<(), std::io::Error> {
+        // Given:
+        let (dir, rules_filepath) = temp_file(
+            "test_rules_file",
+            "- name: private network\n  when:\n    cidr: 10.0.0.0/8\n  then:\n    groups: [private]\n    remove: [LogLevel]\n",
+        )?;
+        let mut cmd = Command::cargo_bin("s2a").unwrap();
+        let ssh_config =
+            "Host web1\n  HostName 10.0.0.1\n  LogLevel FATAL\nHost db1\n  HostName 192.168.0.2\n";
+
+        // When:
+        let assert = cmd
+            .arg("--rules-filepath")
+            .arg(rules_filepath)
+            .arg("--explain-rules")
+            .write_stdin(ssh_config)
+            .assert();
+
+        // Then:
+        assert
+            .success()
+            .code(eq(0))
+            .stdout(eq(r#"local:
+  hosts:
+    db1:
+      ansible_host: 192.168.0.2
+    web1:
+      ansible_host: 10.0.0.1
+  children:
+    private:
+      hosts:
+        web1: {}
+"#))
+            .stderr(eq("web1: private network\ndb1: no rule fired\n"));
+
+        dir.close()?; // clean-up.
+        Ok(())
+    }
+
+    #[test]
+    fn s2a_read_stdin_write_file_with_sidecar_ssh_config() -> Result<(), std::io::Error> {
+        // Given:
+        let (dir, output_filepath) = temp_filepath("test_output_to_file")?;
+        let mut cmd = Command::cargo_bin("s2a").unwrap();
+
+        // When:
+        let assert = cmd
+            .arg("-o")
+            .arg(&output_filepath)
+            .arg("--sidecar")
+            .write_stdin(SAMPLE_SSH_CONFIG)
+            .assert();
+
+        // Then:
+        assert.success().code(eq(0)).stdout(eq(""));
+        let output_string = read_file(&output_filepath)?;
+        assert_eq!(
+            output_string,
+            r#"local:
+  hosts:
+    default: {}
+  vars:
+    ansible_ssh_common_args: -F {{ inventory_dir | quote }}/ssh_config
+"#
+        );
+        let sidecar_string = read_file(&dir.path().join("ssh_config"))?;
+        assert_eq!(
+            sidecar_string,
+            r#"Host default
+  HostKeyAlgorithms +ssh-rsa
+  HostName 127.0.0.1
+  IdentitiesOnly yes
+  IdentityFile /path/to/private_key
+  LogLevel FATAL
+  PasswordAuthentication no
+  Port 50022
+  PubkeyAcceptedKeyTypes +ssh-rsa
+  StrictHostKeyChecking no
+  User vagrant
+  UserKnownHostsFile /dev/null
+"#
+        );
+
+        dir.close()?; // clean-up.
+        Ok(())
+    }
 }